@@ -0,0 +1,80 @@
+//! Force-directed layout of generated trees of thousands of nodes.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use egui::{Pos2, Vec2};
+use weave_writer::{
+    layout::{force_directed, QuadTree, THETA},
+    node::{Meta, Node},
+};
+
+/// A balanced tree of `count` nodes in which each node has `branching`
+/// children, scattered on a sunflower spiral.
+fn tree(count: usize, branching: usize) -> Node<Meta> {
+    let mut nodes: Vec<Node<Meta>> = (0..count)
+        .map(|i| {
+            let angle = i as f32 * 2.4;
+            let radius = 200.0 * (i as f32).sqrt();
+            let mut node = Node::<Meta>::default();
+            node.meta.pos = Pos2::new(angle.cos(), angle.sin()) * radius;
+            node.meta.size = Vec2::new(320.0, 100.0);
+            node
+        })
+        .collect();
+    // Children are moved into their parents last first.
+    for i in (1..count).rev() {
+        let child = nodes.pop().unwrap();
+        nodes[(i - 1) / branching].children.insert(0, child);
+    }
+    nodes.pop().unwrap()
+}
+
+fn layout(c: &mut Criterion) {
+    let mut group = c.benchmark_group("force_directed");
+    for count in [1_000, 4_000, 16_000] {
+        let root = tree(count, 3);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(count),
+            &root,
+            |b, root| {
+                let mut root = root.clone();
+                b.iter(|| {
+                    force_directed(&mut root, 125.0, 2.5, 1.0, 1.0 / 60.0)
+                })
+            },
+        );
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("quadtree");
+    for count in [1_000, 4_000, 16_000] {
+        let bodies: Vec<(Pos2, f32)> = (0..count)
+            .map(|i| {
+                let angle = i as f32 * 2.4;
+                let radius = 200.0 * (i as f32).sqrt();
+                (Pos2::new(angle.cos(), angle.sin()) * radius, 32.0)
+            })
+            .collect();
+        group.bench_with_input(
+            BenchmarkId::new("build", count),
+            &bodies,
+            |b, bodies| b.iter(|| QuadTree::new(bodies)),
+        );
+        let tree = QuadTree::new(&bodies);
+        group.bench_with_input(
+            BenchmarkId::new("field", count),
+            &bodies,
+            |b, bodies| {
+                b.iter(|| {
+                    bodies
+                        .iter()
+                        .map(|&(pos, _)| tree.field(pos, THETA))
+                        .fold(Vec2::ZERO, |sum, field| sum + field)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, layout);
+criterion_main!(benches);
@@ -1,18 +1,31 @@
+mod keybindings;
+mod palette;
 mod settings;
 
 use {
-    self::settings::{BackendOptions, Settings},
+    self::{
+        keybindings::Command,
+        palette::{Argument, Item, Palette, Target},
+        settings::{BackendOptions, Settings},
+    },
     crate::{
         button,
-        node::{Action, Meta, Node},
-        story::{DrawMode, Story},
+        canvas::Canvas,
+        diff::{Comparison, Tag},
+        node::{Action, AuthorIndex, Filter},
+        search::{Field, Hit, Query, Replacement, Scope, Source},
+        story::{Clipping, DrawMode, Step, Story},
     },
 };
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::library::Library;
+
 #[derive(Default, PartialEq, derive_more::Display)]
 pub enum SidebarPage {
     #[default]
     Stories,
+    Search,
     Settings,
 }
 
@@ -25,6 +38,12 @@ struct LeftSidebar {
     /// Whether the story title is being edited. If this is true, the story
     /// title widget is a text edit widget. Otherwise, it is a label.
     pub editing_active_title: bool,
+    /// Full-text search query.
+    pub search: Query,
+    /// Results of the last search.
+    pub hits: Vec<Hit>,
+    /// Why the query failed to compile, if it did.
+    pub search_error: Option<String>,
 }
 
 #[derive(Default, PartialEq)]
@@ -32,6 +51,10 @@ pub enum RightSidebarPage {
     #[default]
     Text,
     Tree,
+    Authors,
+    Replace,
+    Compare,
+    Bookmarks,
 }
 
 impl RightSidebarPage {
@@ -39,9 +62,23 @@ impl RightSidebarPage {
         match self {
             Self::Text => "Text",
             Self::Tree => "Tree",
+            Self::Authors => "Authors",
+            Self::Replace => "Replace",
+            Self::Compare => "Compare",
+            Self::Bookmarks => "Bookmarks",
         }
     }
 }
+
+/// Which branches the compare page shows.
+#[derive(Default, PartialEq)]
+pub enum CompareMode {
+    /// The children of the head.
+    #[default]
+    Children,
+    /// The marked path and the active path.
+    Marked,
+}
 #[derive(Default)]
 struct RightSidebar {
     pub text: Option<String>,
@@ -50,6 +87,26 @@ struct RightSidebar {
     pub model_view: bool,
     pub markdown: bool,
     pub page: RightSidebarPage,
+    /// New author name buffer
+    pub author_buf: String,
+    /// Find and replace query.
+    pub find: Query,
+    /// Replacement text. Capture groups may be referenced as `$1`, etc.
+    pub replace_with: String,
+    /// Which nodes to find and replace in.
+    pub scope: Scope,
+    /// Pending replacements, for preview.
+    pub replacements: Vec<Replacement>,
+    /// Why the find query failed to compile, if it did.
+    pub replace_error: Option<String>,
+    /// Which branches to compare.
+    pub compare_mode: CompareMode,
+    /// Path marked for comparison with the active path.
+    pub compare_mark: Option<Vec<usize>>,
+    /// The last comparison, kept until the compared branches change.
+    pub comparison: Comparison,
+    /// New bookmark name buffer
+    pub bookmark_buf: String,
 }
 
 impl RightSidebar {
@@ -58,14 +115,67 @@ impl RightSidebar {
     /// hasn't changed.
     // TODO: This might not actually be worth it. We should profile first since
     // formatting the story and traversing the tree isn't actually all that
-    // expensive, but it could be if there are many nodes. There is a lot of CPU
-    // usage, but it doesn't seem to be coming from our code. My guess is using
-    // `egui::Window` for each node is part of the problem.
+    // expensive, but it could be if there are many nodes.
     pub fn refresh_story(&mut self) {
         self.text_current = false;
     }
 }
 
+/// Lay out a search result `preview` with the match at `range` in a
+/// `highlight` format.
+fn highlight_preview(
+    preview: &str,
+    range: std::ops::Range<usize>,
+    highlight: egui::TextFormat,
+) -> egui::text::LayoutJob {
+    let mut job = egui::text::LayoutJob::default();
+    job.append(&preview[..range.start], 0.0, Default::default());
+    job.append(&preview[range.clone()], 0.0, highlight);
+    job.append(&preview[range.end..], 0.0, Default::default());
+    job
+}
+
+/// What the save dialog is saving.
+#[derive(Default, Clone, Copy, PartialEq)]
+enum SaveFormat {
+    /// The whole story as JSON.
+    #[default]
+    Json,
+    /// The active path as Markdown.
+    Markdown,
+    /// An attribution report for the active path. The report is written as
+    /// JSON if the chosen file ends in `.json`, otherwise as Markdown.
+    Attribution,
+    /// A fine-tuning dataset from the whole story tree, as JSON Lines.
+    Dataset,
+    /// The branches to the selected nodes as Markdown.
+    Selection,
+}
+
+impl SaveFormat {
+    /// Title of the save dialog.
+    pub fn title(&self) -> &str {
+        match self {
+            Self::Json => "Save Story to JSON",
+            Self::Markdown => "Export Story to Markdown",
+            Self::Attribution => "Export Attribution Report",
+            Self::Dataset => "Export Fine-tuning Dataset",
+            Self::Selection => "Export Selected Branches to Markdown",
+        }
+    }
+
+    /// Extensions to show in the save dialog.
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Self::Json => &["json"],
+            Self::Markdown => &["md"],
+            Self::Attribution => &["md", "json"],
+            Self::Dataset => &["jsonl"],
+            Self::Selection => &["md"],
+        }
+    }
+}
+
 #[derive(derivative::Derivative, thiserror::Error)]
 #[derivative(Debug)]
 #[error("{}", message)]
@@ -100,8 +210,16 @@ pub struct App {
     right_sidebar: RightSidebar,
     last_frame_time: f64,
     time_step: f64,
-    /// Temporary node storage for copy/paste.
-    node_clipboard: Option<Node<Meta>>,
+    /// Temporary node storage for cut/paste. This can be pasted into any
+    /// story.
+    node_clipboard: Option<Clipping>,
+    /// Which nodes to show in the node and tree views.
+    filter: Filter,
+    palette: Palette,
+    /// Pan and zoom of the node view.
+    canvas: Canvas,
+    /// Tag to add to the selected nodes.
+    selection_tag: String,
     /// Modal error messages.
     errors: Vec<Error>,
     /// Commonmark cache
@@ -115,7 +233,54 @@ pub struct App {
     #[cfg(not(target_arch = "wasm32"))]
     save_dialog: Option<egui_file::FileDialog>,
     #[cfg(not(target_arch = "wasm32"))]
-    saving_txt: bool,
+    save_format: SaveFormat,
+    /// Where stories, the trash and settings are saved. If `None`, they're
+    /// saved to eframe storage.
+    #[cfg(not(target_arch = "wasm32"))]
+    library: Option<Library>,
+    /// When changed stories were last written to the recovery directory.
+    #[cfg(not(target_arch = "wasm32"))]
+    last_autosave: f64,
+    /// Stories recovered from a session that ended uncleanly, which the user
+    /// is asked whether to restore.
+    #[cfg(not(target_arch = "wasm32"))]
+    recovery: Option<Vec<Story>>,
+}
+
+/// Seconds between writing changed stories to the recovery directory.
+#[cfg(not(target_arch = "wasm32"))]
+const AUTOSAVE_INTERVAL: f64 = 10.0;
+
+/// Application id. This names the directory where eframe and the [`Library`]
+/// store data.
+pub const APP_ID: &str = "Weave";
+
+/// Load and parse the value stored under `key` in eframe `storage`, or the
+/// default if there is none or it can't be parsed. Errors are added to
+/// `errors`.
+fn load_stored<T>(
+    storage: Option<&dyn eframe::Storage>,
+    key: &str,
+    errors: &mut Vec<Error>,
+) -> T
+where
+    T: serde::de::DeserializeOwned + Default,
+{
+    storage
+        .and_then(|storage| storage.get_string(key))
+        .and_then(|s| {
+            log::debug!("Loading {}: {}", key, s);
+            match serde_json::from_str(&s) {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    errors.push(
+                        format!("Failed to load {} because: {}", key, e).into(),
+                    );
+                    None
+                }
+            }
+        })
+        .unwrap_or_default()
 }
 
 impl App {
@@ -123,80 +288,15 @@ impl App {
         let ctx = cc.egui_ctx.clone();
         let mut errors: Vec<Error> = Vec::new();
 
-        let stories = cc
-            .storage
-            .map(|storage| {
-                storage
-                    .get_string("stories")
-                    .and_then(|s| {
-                        log::debug!("Loading stories: {}", s);
-                        match serde_json::from_str(&s) {
-                            Ok(stories) => Some(stories),
-                            Err(e) => {
-                                errors.push(
-                                    format!(
-                                        "Failed to load stories because: {}",
-                                        e
-                                    )
-                                    .into(),
-                                );
-                                None
-                            }
-                        }
-                    })
-                    .unwrap_or_default()
-            })
-            .unwrap_or_default();
-
-        let trash = cc
-            .storage
-            .map(|storage| {
-                storage
-                    .get_string("trash")
-                    .and_then(|s| {
-                        log::debug!("Loading trash: {}", s);
-                        match serde_json::from_str(&s) {
-                            Ok(trash) => Some(trash),
-                            Err(e) => {
-                                errors.push(
-                                    format!(
-                                        "Failed to load trash because: {}",
-                                        e
-                                    )
-                                    .into(),
-                                );
-                                None
-                            }
-                        }
-                    })
-                    .unwrap_or_default()
-            })
-            .unwrap_or_default();
-
-        let settings = cc
-            .storage
-            .map(|storage| {
-                storage
-                    .get_string("settings")
-                    .and_then(|s| {
-                        log::debug!("Loading settings: {}", s);
-                        match serde_json::from_str(&s) {
-                            Ok(settings) => Some(settings),
-                            Err(e) => {
-                                errors.push(
-                                    format!(
-                                        "Failed to load settings because:{}",
-                                        e
-                                    )
-                                    .into(),
-                                );
-                                None
-                            }
-                        }
-                    })
-                    .unwrap_or_default()
-            })
-            .unwrap_or_default();
+        #[cfg(not(target_arch = "wasm32"))]
+        let (stories, trash, settings, library) =
+            Self::load_library(cc.storage, &mut errors);
+        #[cfg(target_arch = "wasm32")]
+        let (stories, trash, settings) = (
+            load_stored(cc.storage, "stories", &mut errors),
+            load_stored(cc.storage, "trash", &mut errors),
+            load_stored(cc.storage, "settings", &mut errors),
+        );
 
         let mut last_frame_time = 0.0;
         ctx.input(|state| {
@@ -211,9 +311,15 @@ impl App {
             trash,
             last_frame_time,
             time_step: 1.0 / 60.0,
+            errors,
+            #[cfg(not(target_arch = "wasm32"))]
+            library,
             ..Default::default()
         };
 
+        #[cfg(not(target_arch = "wasm32"))]
+        new.begin_session();
+
         // Handle generation backends
         if let Err(e) = new.start_generative_backend(ctx) {
             eprintln!("Failed to start generative backend: {}", e);
@@ -224,6 +330,248 @@ impl App {
         new
     }
 
+    /// Load stories, trash and settings from the [`Library`]. The first time,
+    /// there is no library yet, so they're imported from eframe `storage`,
+    /// where earlier versions kept them, and saved to a new library. If the
+    /// import fails or the library can't be used, eframe storage is used
+    /// instead and the library is `None`. A failed import leaves a copy of the
+    /// old data in the library directory.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_library(
+        storage: Option<&dyn eframe::Storage>,
+        errors: &mut Vec<Error>,
+    ) -> (Vec<Story>, Vec<Story>, Settings, Option<Library>) {
+        let Some(mut library) = eframe::storage_dir(APP_ID)
+            .map(|dir| Library::new(dir.join("library")))
+        else {
+            return (
+                load_stored(storage, "stories", errors),
+                load_stored(storage, "trash", errors),
+                load_stored(storage, "settings", errors),
+                None,
+            );
+        };
+
+        if !library.exists() {
+            let error_count = errors.len();
+            let mut stories: Vec<Story> =
+                load_stored(storage, "stories", errors);
+            let mut trash: Vec<Story> = load_stored(storage, "trash", errors);
+            let settings: Settings = load_stored(storage, "settings", errors);
+            if errors.len() > error_count {
+                // A library without what failed to import would never import
+                // it, so keep using eframe storage. That is overwritten on
+                // save, so keep a copy of what was there.
+                let backup = library.dir().join("import-failed");
+                for key in ["stories", "trash", "settings"] {
+                    let Some(raw) = storage.and_then(|s| s.get_string(key))
+                    else {
+                        continue;
+                    };
+                    let path = backup.join(format!("{}.json", key));
+                    if let Err(e) =
+                        crate::library::write_atomic(&path, raw.as_bytes())
+                    {
+                        errors.push(
+                            format!(
+                                "Failed to back up {} to {} because: {}",
+                                key,
+                                path.display(),
+                                e
+                            )
+                            .into(),
+                        );
+                    }
+                }
+                errors.push(
+                    format!(
+                        "The story library wasn't created because not everything could be imported. A copy of the old data is in {}",
+                        backup.display()
+                    )
+                    .into(),
+                );
+                return (stories, trash, settings, None);
+            }
+            log::info!("Importing stories into {}", library.dir().display());
+            let imported = library
+                .save(&mut stories, &mut trash)
+                .and_then(|_| library.save_settings(&settings));
+            return match imported {
+                Ok(_) => (stories, trash, settings, Some(library)),
+                Err(e) => {
+                    errors.push(
+                        format!(
+                            "Failed to create the story library because: {}",
+                            e
+                        )
+                        .into(),
+                    );
+                    (stories, trash, settings, None)
+                }
+            };
+        }
+
+        let loaded = match library.load() {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                // Saving now would overwrite whatever is in the library.
+                errors.push(
+                    format!(
+                        "Failed to load the story library, so it won't be saved to, because: {}",
+                        e
+                    )
+                    .into(),
+                );
+                return (
+                    load_stored(storage, "stories", errors),
+                    load_stored(storage, "trash", errors),
+                    load_stored(storage, "settings", errors),
+                    None,
+                );
+            }
+        };
+        errors.extend(
+            loaded.errors.into_iter().map(|e| {
+                format!("Failed to load a story because: {}", e).into()
+            }),
+        );
+        let settings = match library.load_settings() {
+            Ok(Some(settings)) => settings,
+            Ok(None) => load_stored(storage, "settings", errors),
+            Err(e) => {
+                errors.push(
+                    format!("Failed to load settings because: {}", e).into(),
+                );
+                Settings::default()
+            }
+        };
+
+        (loaded.stories, loaded.trash, settings, Some(library))
+    }
+
+    /// Start a library session. If the last one ended uncleanly, stories with
+    /// unsaved changes are offered for recovery.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn begin_session(&mut self) {
+        let Some(library) = &mut self.library else {
+            return;
+        };
+        match library.begin_session().and_then(|unclean| {
+            if unclean {
+                library.load_recovery().map(Some)
+            } else {
+                library.clear_recovery().map(|_| None)
+            }
+        }) {
+            Ok(Some(loaded)) => {
+                self.errors.extend(loaded.errors.into_iter().map(|e| {
+                    format!("Failed to recover a story because: {}", e).into()
+                }));
+                if !loaded.stories.is_empty() {
+                    self.recovery = Some(loaded.stories);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => self.errors.push(
+                format!(
+                    "Failed to check for stories to recover because: {}",
+                    e
+                )
+                .into(),
+            ),
+        }
+    }
+
+    /// Every [`AUTOSAVE_INTERVAL`], check which stories have unsaved changes
+    /// and write copies of those that haven't been saved to the library to the
+    /// recovery directory.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn autosave(&mut self, ctx: &egui::Context) {
+        let now = ctx.input(|input| input.time);
+        if now - self.last_autosave < AUTOSAVE_INTERVAL {
+            return;
+        }
+        self.last_autosave = now;
+        // Saving recovery copies also checks which stories are dirty.
+        let Some(library) = &mut self.library else {
+            for story in self.stories.iter_mut() {
+                story.check_dirty();
+            }
+            return;
+        };
+        if let Err(e) = library.save_recovery(&mut self.stories) {
+            // This is retried, so an error every time would be a nuisance.
+            log::error!("Failed to autosave because: {}", e);
+        }
+    }
+
+    /// Ask whether to restore the stories recovered from the last session.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_recovery_prompt(&mut self, ctx: &egui::Context) {
+        let Some(recovery) = &self.recovery else {
+            return;
+        };
+        let mut recover = None;
+        egui::Window::new("Recover unsaved changes")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(
+                    "Weave didn't close cleanly last time. These stories had changes that weren't saved:",
+                );
+                for story in recovery {
+                    ui.label(format!("• {}", story.title));
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Recover").clicked() {
+                        recover = Some(true);
+                    }
+                    if ui.button("Discard").clicked() {
+                        recover = Some(false);
+                    }
+                });
+            });
+
+        match recover {
+            Some(true) => {
+                // Recovered stories replace the ones they're copies of. They
+                // are saved to the library, and the copies removed, the next
+                // time the library is saved.
+                for mut story in self.recovery.take().unwrap_or_default() {
+                    match self
+                        .stories
+                        .iter_mut()
+                        .find(|s| s.library_file == story.library_file)
+                    {
+                        Some(existing) => {
+                            if let Some(path) = existing.path() {
+                                story.set_path(path);
+                            }
+                            *existing = story;
+                        }
+                        None => self.stories.push(story),
+                    }
+                }
+            }
+            Some(false) => {
+                self.recovery = None;
+                if let Some(Err(e)) =
+                    self.library.as_mut().map(Library::clear_recovery)
+                {
+                    self.errors.push(
+                        format!(
+                            "Failed to discard recovered stories because: {}",
+                            e
+                        )
+                        .into(),
+                    );
+                }
+            }
+            None => {}
+        }
+    }
+
     /// Update frame time.
     pub fn update_time_step(&mut self, ctx: &egui::Context) {
         let mut this_frame_time = 0.0;
@@ -242,6 +590,17 @@ impl App {
         self.active_story = Some(self.stories.len() - 1);
     }
 
+    /// Copy the active path of the active story (and everything below the
+    /// head) to a new story and make it active.
+    pub fn branch_to_new_story(&mut self) {
+        if let Some(story) = self.story() {
+            let title = format!("{} (branch)", story.title);
+            let branch = story.branch_to_story(story.active_path(), title);
+            self.stories.push(branch);
+            self.active_story = Some(self.stories.len() - 1);
+        }
+    }
+
     /// (active) story
     pub fn story(&self) -> Option<&Story> {
         self.active_story.map(|i| self.stories.get(i))?
@@ -329,7 +688,15 @@ impl App {
                     // are mutably borrowed. We don't use `backend_options`
                     // after this, so it's fine.
                     let story = if let Some(story) = self.story_mut() {
-                        story.add_author(model_name);
+                        let id = story.add_model_author(model_name);
+                        // A new (empty) node is credited to the model. When
+                        // continuing an existing node, or if there are too
+                        // many authors, the author is kept.
+                        if let Some(id) =
+                            id.filter(|_| story.head().text.is_empty())
+                        {
+                            story.head_mut().author_id = id;
+                        }
                         story
                     } else {
                         // This should not happen.
@@ -367,7 +734,15 @@ impl App {
                     let mut options = settings.chat_arguments.clone();
 
                     let story = if let Some(story) = self.story_mut() {
-                        story.add_author(model_name);
+                        let id = story.add_model_author(model_name);
+                        // A new (empty) node is credited to the model. When
+                        // continuing an existing node, or if there are too
+                        // many authors, the author is kept.
+                        if let Some(id) =
+                            id.filter(|_| story.head().text.is_empty())
+                        {
+                            story.head_mut().author_id = id;
+                        }
                         story
                     } else {
                         // This should not happen.
@@ -498,6 +873,11 @@ impl App {
                         SidebarPage::Stories,
                         "Stories",
                     );
+                    ui.selectable_value(
+                        &mut self.left_sidebar.page,
+                        SidebarPage::Search,
+                        "Search",
+                    );
                     ui.selectable_value(
                         &mut self.left_sidebar.page,
                         SidebarPage::Settings,
@@ -512,10 +892,21 @@ impl App {
                         if let Some(action) = self.settings.draw(ui) {
                             self.handle_settings_action(action, ctx);
                         }
+                        #[cfg(not(target_arch = "wasm32"))]
+                        if let Some(library) = &self.library {
+                            ui.separator();
+                            ui.label(format!(
+                                "Stories are saved in {}",
+                                library.dir().display()
+                            ));
+                        }
                     }
                     SidebarPage::Stories => {
                         self.draw_stories_tab(ui);
                     }
+                    SidebarPage::Search => {
+                        self.draw_search_tab(ui);
+                    }
                 }
             });
     }
@@ -550,6 +941,26 @@ impl App {
                         RightSidebarPage::Tree,
                         "As Tree",
                     );
+                    ui.selectable_value(
+                        &mut self.right_sidebar.page,
+                        RightSidebarPage::Authors,
+                        "Authors",
+                    );
+                    ui.selectable_value(
+                        &mut self.right_sidebar.page,
+                        RightSidebarPage::Replace,
+                        "Replace",
+                    );
+                    ui.selectable_value(
+                        &mut self.right_sidebar.page,
+                        RightSidebarPage::Compare,
+                        "Compare",
+                    );
+                    ui.selectable_value(
+                        &mut self.right_sidebar.page,
+                        RightSidebarPage::Bookmarks,
+                        "Bookmarks",
+                    );
                 });
 
                 ui.heading(self.right_sidebar.page.as_str());
@@ -628,16 +1039,38 @@ impl App {
                         }
                     }
                     RightSidebarPage::Tree => {
+                        self.draw_tree_filter(ui);
                         let lock_topology = self.generation_ui_locked;
                         let layout = self.settings.layout.clone();
+                        let filter = self.filter.clone();
                         if let Some(story) = self.story_mut() {
                             if let Some(action) =
-                                story.draw(ui, lock_topology, layout, DrawMode::Tree, time_step)
+                                story.draw(ui, lock_topology, layout, DrawMode::Tree, time_step, &filter)
                             {
                                 self.handle_story_action(action);
                             }
                         }
                     }
+                    RightSidebarPage::Authors => {
+                        let mut author_buf =
+                            std::mem::take(&mut self.right_sidebar.author_buf);
+                        let modified = self
+                            .story_mut()
+                            .is_some_and(|s| s.draw_authors(ui, &mut author_buf));
+                        self.right_sidebar.author_buf = author_buf;
+                        if modified {
+                            self.right_sidebar.refresh_story();
+                        }
+                    }
+                    RightSidebarPage::Replace => {
+                        self.draw_replace_tab(ui);
+                    }
+                    RightSidebarPage::Compare => {
+                        self.draw_compare_tab(ui);
+                    }
+                    RightSidebarPage::Bookmarks => {
+                        self.draw_bookmarks_tab(ui);
+                    }
                 }
             });
     }
@@ -684,7 +1117,12 @@ impl App {
                 .add(egui::Button::image(egui::include_image!(
                     "../resources/save.png"
                 )))
-                .on_hover_text_at_pointer("Save story to JSON.");
+                .on_hover_text_at_pointer(
+                    "Save story to its JSON file, or choose one.",
+                );
+            let save_as = ui
+                .button("Save as")
+                .on_hover_text_at_pointer("Save story to a new JSON file.");
 
             let export = ui
                 .add(egui::Button::image(egui::include_image!(
@@ -698,14 +1136,39 @@ impl App {
                     "../resources/load.png"
                 )))
                 .on_hover_text_at_pointer("Load story from JSON.");
+            ui.checkbox(&mut self.settings.export_notes, "Notes")
+                .on_hover_text_at_pointer(
+                    "Include node notes in Markdown exports.",
+                );
+            let attribution = ui
+                .button("Credits")
+                .on_hover_text_at_pointer(
+                    "Export who wrote what along the active path, as Markdown or JSON.",
+                );
 
             // only one can happen per frame realistically
             if save_btn.clicked() {
+                self.save_in_place();
+            } else if save_as.clicked() {
                 self.save_to_json();
             } else if load_btn.clicked() {
                 self.load_from_json();
             } else if export.clicked() {
                 self.export_to_markdown();
+            } else if attribution.clicked() {
+                self.export_attribution();
+            }
+        });
+        egui::CollapsingHeader::new("Dataset export").show(ui, |ui| {
+            self.settings.dataset.ui(ui);
+            if ui
+                .button("Export")
+                .on_hover_text_at_pointer(
+                    "Export the story tree as a JSON Lines fine-tuning dataset.",
+                )
+                .clicked()
+            {
+                self.save(SaveFormat::Dataset);
             }
         });
     }
@@ -784,6 +1247,7 @@ impl App {
         #[cfg(not(target_arch = "wasm32"))]
         {
             self.draw_save_buttons(ui);
+            self.draw_recent_files(ui);
             ui.separator();
         }
         let mut delete = None;
@@ -791,6 +1255,12 @@ impl App {
             ui.horizontal(|ui| {
                 let active_story =
                     self.active_story.is_some_and(|idx| idx == i);
+                // Stories with unsaved changes are marked.
+                let title = if story.is_dirty() {
+                    format!("● {}", story.title)
+                } else {
+                    story.title.clone()
+                };
                 if button!(ui, "../resources/delete.png").clicked() {
                     delete = Some(i);
                 }
@@ -805,11 +1275,11 @@ impl App {
                         {
                             self.left_sidebar.editing_active_title = false;
                         }
-                    } else if ui.button(&story.title).clicked() {
+                    } else if ui.button(title).clicked() {
                         self.left_sidebar.editing_active_title = true;
                     }
                 } else {
-                    if ui.button(&story.title).clicked() {
+                    if ui.button(title).clicked() {
                         self.active_story = Some(i);
                         self.left_sidebar.editing_active_title = false;
                     }
@@ -881,96 +1351,628 @@ impl App {
         }
     }
 
-    /// Draw the central panel.
-    pub fn draw_central_panel(
-        &mut self,
-        ctx: &eframe::egui::Context,
-        _frame: &mut eframe::Frame,
-    ) {
-        let time_step = self.time_step as f32;
+    /// Search all stories and the trash for the query in the left sidebar.
+    fn search(&mut self) {
+        let sidebar = &mut self.left_sidebar;
+        sidebar.hits.clear();
+        sidebar.search_error = None;
+        if sidebar.search.text.is_empty() {
+            return;
+        }
+        match sidebar.search.compile() {
+            Ok(regex) => {
+                sidebar.hits =
+                    crate::search::search(&self.stories, &self.trash, &regex);
+            }
+            Err(e) => sidebar.search_error = Some(e.to_string()),
+        }
+    }
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let mut new_pieces = Vec::new();
+    /// Make the node of a search `hit` the head of the active story. Stories
+    /// in the trash are restored first.
+    fn jump_to(&mut self, hit: Hit) {
+        let index = match hit.source {
+            Source::Stories => hit.story,
+            Source::Trash => {
+                if hit.story >= self.trash.len() {
+                    return;
+                }
+                self.stories.push(self.trash.remove(hit.story));
+                // Indices into the trash are now stale.
+                self.search();
+                self.stories.len() - 1
+            }
+        };
 
-            self.update_generation(&mut new_pieces);
+        let found = self
+            .stories
+            .get_mut(index)
+            .is_some_and(|story| story.set_active_path(hit.path));
+        if found {
+            self.active_story = Some(index);
+            self.left_sidebar.editing_active_title = false;
+            self.right_sidebar.refresh_story();
+        } else {
+            self.errors
+                .push("Search result is out of date. Search again.".into());
+        }
+    }
 
-            // TODO: make it possible to scroll the node view. The nodes are
-            // currently windows which cannot be in a scroll area. They float.
-            // It would have been nice to know this before, but oh well. One
-            // solution suggested in the following issue is to use an area
-            // within an area:
-            // https://github.com/emilk/egui/discussions/3290
-            // Another is to make a custom widget. Either is a bunch of work,
-            // but the latter might be more flexible. `Window` also does a lot
-            // we don't actually need.
-            // Probably less work is actually use `wgpu` to render the nodes in
-            // the viewport. It's less work than it sounds, and probably less
-            // than the other solutions which might integrate better with egui,
-            // but might be more work to implement and maintain. A `wgpu`
-            // solution might perform better as well and I have some experience
-            // with it.
-            // In the meantime, the windows are, at least, collapsible.
-            let generation_in_progress = self.generation_ui_locked;
-            let layout = self.settings.layout.clone();
-            let mut update_right_sidebar = false;
-            if let Some(story) = self.story_mut() {
-                if !new_pieces.is_empty() {
-                    story.extend_paragraph(new_pieces);
-                    update_right_sidebar = true;
-                }
+    fn draw_search_tab(&mut self, ui: &mut egui::Ui) {
+        let response = self.left_sidebar.search.ui(ui);
+        if response.changed()
+            || ui
+                .button("Search")
+                .on_hover_text_at_pointer("Search all stories and the trash.")
+                .clicked()
+        {
+            self.search();
+        }
 
-                // TODO: the response from story.draw could be more succinct. We
-                // only really know if we need to start generation (for now).
-                if let Some(action) = story.draw(
-                    ui,
-                    generation_in_progress,
-                    layout,
-                    DrawMode::Nodes,
-                    time_step,
-                ) {
-                    self.handle_story_action(action)
-                }
+        if let Some(error) = &self.left_sidebar.search_error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+            return;
+        }
 
-                if update_right_sidebar {
-                    self.right_sidebar.refresh_story();
+        ui.label(format!("{} matches", self.left_sidebar.hits.len()));
+        ui.separator();
+
+        let mut jump = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            let highlight = egui::TextFormat {
+                background: ui.visuals().selection.bg_fill,
+                ..Default::default()
+            };
+            for hit in self.left_sidebar.hits.iter() {
+                let story = match hit.source {
+                    Source::Stories => self.stories.get(hit.story),
+                    Source::Trash => self.trash.get(hit.story),
+                };
+                let Some(story) = story else {
+                    continue;
+                };
+
+                let mut heading = story.title.clone();
+                match hit.field {
+                    Field::Text => {}
+                    Field::Tag(_) => heading.push_str(" (tag)"),
+                    Field::Notes => heading.push_str(" (notes)"),
                 }
-            } else {
-                if !new_pieces.is_empty() {
-                    // We received a piece of text but there is no active story.
-                    // This should not happen.
-                    eprintln!(
-                        "Received pieces but no active story: {new_pieces:?}"
-                    );
+                if hit.source == Source::Trash {
+                    heading.push_str(" (trash)");
                 }
-                ui.heading("Welcome to Weave!");
-                egui_commonmark::commonmark_str!(
-                    "welcome",
-                    ui,
-                    &mut self.commonmark_cache,
-                    "resources/SHORTCUTS.md"
+                ui.label(egui::RichText::new(heading).strong());
+
+                let job = highlight_preview(
+                    &hit.preview,
+                    hit.preview_range.clone(),
+                    highlight.clone(),
                 );
+
+                let path = hit
+                    .path
+                    .iter()
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" › ");
+                if ui
+                    .add(egui::Label::new(job).sense(egui::Sense::click()))
+                    .on_hover_text_at_pointer(format!("Path: root › {}", path))
+                    .clicked()
+                {
+                    jump = Some(hit.clone());
+                }
+                ui.separator();
             }
         });
-    }
-
-    /// Handle path action.
-    pub fn handle_story_action(&mut self, action: Action) {
-        let mut start_generation = false;
-        let mut update_right_sidebar = false;
 
-        if action.continue_ | action.generate.is_some() {
-            // The path has already been changed. We need only
-            // start generation.
-            start_generation = true;
-        }
-        if action.modified {
-            update_right_sidebar = true;
+        if let Some(hit) = jump {
+            self.jump_to(hit);
         }
+    }
 
-        if start_generation {
-            if let Err(e) = self.start_generation() {
-                self.errors.push(
-                    format!("Failed to start generation because: {}", e).into(),
+    /// Find matches in the active story for the query in the right sidebar.
+    fn find_replacements(&mut self) {
+        let sidebar = &mut self.right_sidebar;
+        sidebar.replacements.clear();
+        sidebar.replace_error = None;
+        let Some(story) = self.active_story.and_then(|i| self.stories.get(i))
+        else {
+            return;
+        };
+        if sidebar.find.text.is_empty() {
+            return;
+        }
+        match sidebar.find.compile() {
+            Ok(regex) => {
+                sidebar.replacements = crate::search::find_replacements(
+                    story,
+                    sidebar.scope,
+                    &regex,
+                    &sidebar.replace_with,
+                );
+            }
+            Err(e) => sidebar.replace_error = Some(e.to_string()),
+        }
+    }
+
+    fn draw_replace_tab(&mut self, ui: &mut egui::Ui) {
+        let mut changed = self.right_sidebar.find.ui(ui).changed();
+        changed |= ui
+            .add(
+                egui::TextEdit::singleline(
+                    &mut self.right_sidebar.replace_with,
+                )
+                .hint_text("Replace with")
+                .desired_width(f32::INFINITY),
+            )
+            .on_hover_text_at_pointer(
+                "With regex, capture groups may be used as $1, $name, etc.",
+            )
+            .changed();
+        ui.horizontal(|ui| {
+            for scope in Scope::ALL {
+                changed |= ui
+                    .selectable_value(
+                        &mut self.right_sidebar.scope,
+                        scope,
+                        scope.as_str(),
+                    )
+                    .changed();
+            }
+        });
+        if changed || ui.button("Preview").clicked() {
+            self.find_replacements();
+        }
+
+        if let Some(error) = &self.right_sidebar.replace_error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+            return;
+        }
+
+        let enabled = self
+            .right_sidebar
+            .replacements
+            .iter()
+            .filter(|r| r.enabled)
+            .count();
+        let mut apply = false;
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{} of {} selected",
+                enabled,
+                self.right_sidebar.replacements.len()
+            ));
+            apply = ui
+                .add_enabled(
+                    enabled > 0 && !self.generation_ui_locked,
+                    egui::Button::new("Replace"),
+                )
+                .clicked();
+        });
+        ui.separator();
+
+        if apply {
+            // Matches are invalid once applied, so they're cleared.
+            let replacements =
+                std::mem::take(&mut self.right_sidebar.replacements);
+            if let Some(story) = self.story_mut() {
+                crate::search::apply_replacements(story, &replacements);
+            }
+            self.right_sidebar.refresh_story();
+            return;
+        }
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            let removed = egui::TextFormat {
+                strikethrough: egui::Stroke::new(
+                    1.0,
+                    ui.visuals().error_fg_color,
+                ),
+                ..Default::default()
+            };
+            let added = egui::TextFormat {
+                background: ui.visuals().selection.bg_fill,
+                ..Default::default()
+            };
+            for replacement in self.right_sidebar.replacements.iter_mut() {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut replacement.enabled, "");
+                    let range = replacement.preview_range.clone();
+                    let mut job = highlight_preview(
+                        &replacement.preview[..range.end],
+                        range.clone(),
+                        removed.clone(),
+                    );
+                    job.append(&replacement.with, 0.0, added.clone());
+                    job.append(
+                        &replacement.preview[range.end..],
+                        0.0,
+                        Default::default(),
+                    );
+                    ui.add(egui::Label::new(job).wrap(true));
+                });
+                ui.separator();
+            }
+        });
+    }
+
+    /// Move the active story's head one [`Step`].
+    pub fn step(&mut self, step: Step) {
+        if self.generation_ui_locked {
+            return;
+        }
+        if let Some(story) = self.story_mut() {
+            if story.step(step) {
+                self.right_sidebar.refresh_story();
+            }
+        }
+    }
+
+    /// Generate a new child of the head, or `continue_` the head itself.
+    pub fn generate_at_head(&mut self, continue_: bool) {
+        if self.generation_ui_locked {
+            return;
+        }
+        let Some(story) = self.story_mut() else {
+            return;
+        };
+        let action = if continue_ {
+            Action {
+                continue_: true,
+                ..Default::default()
+            }
+        } else {
+            let child = story.head_mut().new_child_below();
+            let path = [story.active_path(), &[child]].concat();
+            story.set_active_path(path);
+            Action {
+                generate: Some(child),
+                ..Default::default()
+            }
+        };
+        self.handle_story_action(action);
+    }
+
+    /// Go back (or `forward`) in the active story's navigation history.
+    pub fn navigate_history(&mut self, forward: bool) {
+        if self.generation_ui_locked {
+            return;
+        }
+        if let Some(story) = self.story_mut() {
+            let moved = if forward {
+                story.go_forward()
+            } else {
+                story.go_back()
+            };
+            if moved {
+                self.right_sidebar.refresh_story();
+            }
+        }
+    }
+
+    /// Make the node bookmarked at `index` in the active story the head.
+    pub fn go_to_bookmark(&mut self, index: usize) {
+        if self.generation_ui_locked {
+            return;
+        }
+        if let Some(story) = self.story_mut() {
+            if story.go_to_bookmark(index) {
+                self.right_sidebar.refresh_story();
+            }
+        }
+    }
+
+    /// Bookmark the head of the active story. If `name` is empty, a name is
+    /// made up.
+    pub fn bookmark_head(&mut self, name: String) {
+        if let Some(story) = self.story_mut() {
+            let name = if name.trim().is_empty() {
+                format!("Bookmark {}", story.bookmarks().len() + 1)
+            } else {
+                name.trim().to_string()
+            };
+            story.add_bookmark(name);
+        }
+    }
+
+    fn draw_bookmarks_tab(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if button!(ui, "../resources/add.png")
+                .on_hover_text_at_pointer(
+                    "Bookmark the selected node. A bookmark with the same name is moved.",
+                )
+                .clicked()
+            {
+                let name = std::mem::take(&mut self.right_sidebar.bookmark_buf);
+                self.bookmark_head(name);
+            }
+            ui.add(
+                egui::TextEdit::singleline(&mut self.right_sidebar.bookmark_buf)
+                    .hint_text("Bookmark name"),
+            );
+        });
+        ui.separator();
+
+        let Some(story) = self.story() else {
+            return;
+        };
+        let head = story.head().meta.id();
+        let mut go_to = None;
+        let mut remove = None;
+        for (i, bookmark) in story.bookmarks().iter().enumerate() {
+            let found = story.find_node(bookmark.id).is_some();
+            ui.horizontal(|ui| {
+                if button!(ui, "../resources/delete.png")
+                    .on_hover_text_at_pointer("Remove bookmark.")
+                    .clicked()
+                {
+                    remove = Some(i);
+                }
+                let mut button = ui.add_enabled(
+                    found && !self.generation_ui_locked,
+                    egui::SelectableLabel::new(
+                        bookmark.id == head,
+                        &bookmark.name,
+                    ),
+                );
+                if !found {
+                    button = button.on_disabled_hover_text(
+                        "The bookmarked node has been deleted.",
+                    );
+                } else if let Some(shortcut) =
+                    u8::try_from(i).ok().and_then(|i| {
+                        self.settings
+                            .keybindings
+                            .shortcuts(Command::GoToBookmark(i))
+                            .next()
+                    })
+                {
+                    button = button.on_hover_text_at_pointer(
+                        ui.ctx().format_shortcut(shortcut),
+                    );
+                }
+                if button.clicked() {
+                    go_to = Some(i);
+                }
+            });
+        }
+
+        if let Some(i) = go_to {
+            self.go_to_bookmark(i);
+        }
+        if let Some(i) = remove {
+            if let Some(story) = self.story_mut() {
+                story.remove_bookmark(i);
+            }
+        }
+    }
+
+    fn draw_compare_tab(&mut self, ui: &mut egui::Ui) {
+        let Some(story) = self.story() else {
+            return;
+        };
+        let head_path = story.active_path().to_vec();
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(
+                &mut self.right_sidebar.compare_mode,
+                CompareMode::Children,
+                "Children",
+            )
+            .on_hover_text_at_pointer("Compare the children of the head.");
+            ui.selectable_value(
+                &mut self.right_sidebar.compare_mode,
+                CompareMode::Marked,
+                "Marked",
+            )
+            .on_hover_text_at_pointer("Compare the marked path with the head.");
+            if ui
+                .button("Mark head")
+                .on_hover_text_at_pointer("Mark the head for comparison.")
+                .clicked()
+            {
+                self.right_sidebar.compare_mark = Some(head_path.clone());
+                self.right_sidebar.compare_mode = CompareMode::Marked;
+            }
+        });
+
+        let Some(story) = self.story() else {
+            return;
+        };
+        let paths = match self.right_sidebar.compare_mode {
+            CompareMode::Children => Comparison::child_paths(story, &head_path),
+            CompareMode::Marked => match &self.right_sidebar.compare_mark {
+                Some(mark) => vec![mark.clone(), head_path],
+                None => {
+                    ui.label("Mark a node, then select another to compare.");
+                    return;
+                }
+            },
+        };
+        // Diffing every frame would be slow.
+        if !self.right_sidebar.comparison.is_current(story, &paths) {
+            self.right_sidebar.comparison = Comparison::new(story, paths);
+        }
+        let comparison = &self.right_sidebar.comparison;
+        if comparison.branches.len() < 2 {
+            ui.label("Nothing to compare.");
+            return;
+        }
+        ui.label(format!(
+            "Shared path: {} nodes",
+            comparison.shared_path.len() + 1
+        ));
+        ui.separator();
+
+        let removed = egui::TextFormat {
+            background: ui.visuals().error_fg_color.gamma_multiply(0.25),
+            ..Default::default()
+        };
+        let added = egui::TextFormat {
+            background: ui.visuals().selection.bg_fill,
+            ..Default::default()
+        };
+
+        let mut new_head = None;
+        egui::ScrollArea::both().show(ui, |ui| {
+            ui.columns(comparison.branches.len(), |columns| {
+                for (i, (ui, branch)) in
+                    columns.iter_mut().zip(&comparison.branches).enumerate()
+                {
+                    ui.horizontal(|ui| {
+                        let label = if i == 0 {
+                            "Baseline".to_string()
+                        } else {
+                            format!("Branch {}", i)
+                        };
+                        ui.strong(label);
+                        if ui
+                            .button("Make head")
+                            .on_hover_text_at_pointer(
+                                "Make the end of this branch the head.",
+                            )
+                            .clicked()
+                        {
+                            new_head = Some(branch.path.clone());
+                        }
+                    });
+                    let stats = branch.stats;
+                    ui.label(format!(
+                        "{} chars, {} words\nshared prefix: {} chars\nsimilarity: {:.0}%",
+                        stats.chars,
+                        stats.words,
+                        stats.shared_prefix,
+                        stats.similarity * 100.0
+                    ));
+                    ui.separator();
+
+                    let mut job = egui::text::LayoutJob::default();
+                    for span in branch.spans.iter() {
+                        let format = match span.tag {
+                            Tag::Same => Default::default(),
+                            Tag::Removed => removed.clone(),
+                            Tag::Added => added.clone(),
+                        };
+                        job.append(&span.text, 0.0, format);
+                    }
+                    ui.add(egui::Label::new(job).wrap(true));
+                }
+            });
+        });
+
+        if let Some(path) = new_head {
+            if !self.generation_ui_locked {
+                if let Some(story) = self.story_mut() {
+                    story.set_active_path(path);
+                }
+                self.right_sidebar.refresh_story();
+            }
+        }
+    }
+
+    /// Draw the central panel.
+    pub fn draw_central_panel(
+        &mut self,
+        ctx: &eframe::egui::Context,
+        _frame: &mut eframe::Frame,
+    ) {
+        let time_step = self.time_step as f32;
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let mut new_pieces = Vec::new();
+
+            self.update_generation(&mut new_pieces);
+
+            let generation_in_progress = self.generation_ui_locked;
+            let layout = self.settings.layout.clone();
+            let filter = self.filter.clone();
+            let mut update_right_sidebar = false;
+            // The story and canvas are borrowed separately.
+            let story = self.active_story.and_then(|i| self.stories.get_mut(i));
+            if let Some(story) = story {
+                if !new_pieces.is_empty() {
+                    story.extend_paragraph(new_pieces);
+                    update_right_sidebar = true;
+                }
+
+                // Keep the head in view however it was selected.
+                let head = &story.head().meta;
+                self.canvas.follow(head.id(), head.rect());
+
+                // TODO: the response from story.draw could be more succinct. We
+                // only really know if we need to start generation (for now).
+                let action = self.canvas.show(ui, |ui| {
+                    story.draw(
+                        ui,
+                        generation_in_progress,
+                        layout,
+                        DrawMode::Nodes,
+                        time_step,
+                        &filter,
+                    )
+                });
+                if let Some(rect) = self.canvas.take_lasso() {
+                    story.select_within(rect);
+                }
+                if !self.settings.hide_minimap {
+                    // Nodes on the active path are highlighted.
+                    let active_path = story.active_path();
+                    let rects = story.root().iter_depth_first_paths().map(
+                        |(path, node)| {
+                            (node.meta.rect(), active_path.starts_with(&path))
+                        },
+                    );
+                    self.canvas.minimap(ui, rects);
+                }
+                if let Some(action) = action {
+                    self.handle_story_action(action)
+                }
+
+                if update_right_sidebar {
+                    self.right_sidebar.refresh_story();
+                }
+            } else {
+                if !new_pieces.is_empty() {
+                    // We received a piece of text but there is no active story.
+                    // This should not happen.
+                    eprintln!(
+                        "Received pieces but no active story: {new_pieces:?}"
+                    );
+                }
+                ui.heading("Welcome to Weave!");
+                // Generated from the live keybindings so it can't drift.
+                let help = self
+                    .settings
+                    .keybindings
+                    .help(|shortcut| ctx.format_shortcut(shortcut));
+                egui_commonmark::CommonMarkViewer::new("welcome").show(
+                    ui,
+                    &mut self.commonmark_cache,
+                    &help,
+                );
+            }
+        });
+    }
+
+    /// Handle path action.
+    pub fn handle_story_action(&mut self, action: Action) {
+        let mut start_generation = false;
+        let mut update_right_sidebar = false;
+
+        if action.continue_ | action.generate.is_some() {
+            // The path has already been changed. We need only
+            // start generation.
+            start_generation = true;
+        }
+        if action.modified {
+            update_right_sidebar = true;
+        }
+
+        if start_generation {
+            if let Err(e) = self.start_generation() {
+                self.errors.push(
+                    format!("Failed to start generation because: {}", e).into(),
                 );
             }
         }
@@ -1157,38 +2159,146 @@ impl App {
         }
     }
 
-    /// Save active story to JSON.
+    /// Open the story in the JSON file at `path` and make it active. If a
+    /// story from that file is already open, it's made active instead. The
+    /// file is added to the recent files, or removed if it can't be opened.
+    /// Returns true if the story was opened.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(&mut self, path: &std::path::Path) -> bool {
+        // The same file can be named many ways.
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if let Some(i) = self
+            .stories
+            .iter()
+            .position(|story| story.path() == Some(path.as_path()))
+        {
+            self.active_story = Some(i);
+            self.settings.add_recent_file(path);
+            return true;
+        }
+
+        let story = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read `{:?}` because: {}", path, e))
+            .and_then(|text| {
+                serde_json::from_str::<Story>(&text).map_err(|e| {
+                    format!("Failed to parse `{:?}` because: {}", path, e)
+                })
+            });
+        match story {
+            Ok(mut story) => {
+                // Saving in place writes back to the file.
+                let fingerprint = story.fingerprint();
+                story.set_path(&path);
+                story.mark_saved(fingerprint);
+                self.stories.push(story);
+                self.active_story = Some(self.stories.len() - 1);
+                self.settings.add_recent_file(path);
+                true
+            }
+            Err(e) => {
+                self.errors.push(e.into());
+                self.settings.recent_files.retain(|p| *p != path);
+                false
+            }
+        }
+    }
+
+    /// Draw the recent files. Clicking one opens it.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn draw_recent_files(&mut self, ui: &mut egui::Ui) {
+        if self.settings.recent_files.is_empty() {
+            return;
+        }
+        let mut open = None;
+        egui::CollapsingHeader::new("Recent files").show(ui, |ui| {
+            for path in &self.settings.recent_files {
+                let name = path
+                    .file_name()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy();
+                if ui
+                    .button(name)
+                    .on_hover_text_at_pointer(path.display().to_string())
+                    .clicked()
+                {
+                    open = Some(path.clone());
+                }
+            }
+            if ui.small_button("Clear").clicked() {
+                self.settings.recent_files.clear();
+            }
+        });
+        if let Some(path) = open {
+            self.open(&path);
+        }
+    }
+
+    /// Save active story to JSON, choosing where.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn save_to_json(&mut self) {
-        self.save(true)
+        self.save(SaveFormat::Json)
+    }
+
+    /// Save active story to the JSON file it was loaded from or last saved
+    /// to. If there is none, choose where as with [`App::save_to_json`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_in_place(&mut self) {
+        let Some(story) = self.story_mut() else {
+            return;
+        };
+        let Some(path) = story.path().map(std::path::Path::to_path_buf) else {
+            return self.save_to_json();
+        };
+        let fingerprint = story.fingerprint();
+        let written = serde_json::to_string(&*story)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                crate::library::write_atomic(&path, json.as_bytes())
+                    .map_err(|e| e.to_string())
+            });
+        match written {
+            Ok(_) => story.mark_saved(fingerprint),
+            Err(e) => self.errors.push(
+                format!("Failed to write `{:?}` because: {}", path, e).into(),
+            ),
+        }
     }
 
     /// Export active story to Markdown.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn export_to_markdown(&mut self) {
-        self.save(false)
+        self.save(SaveFormat::Markdown)
+    }
+
+    /// Export an attribution report for the active story.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export_attribution(&mut self) {
+        self.save(SaveFormat::Attribution)
     }
 
-    /// Helper function for `save_to_json` and `export_to_markdown`.
+    /// Export the branches to the selected nodes of the active story.
     #[cfg(not(target_arch = "wasm32"))]
-    fn save(&mut self, json: bool) {
+    pub fn export_selection(&mut self) {
+        self.save(SaveFormat::Selection)
+    }
+
+    /// Helper function for `save_to_json`, `export_to_markdown`,
+    /// `export_attribution`, and `export_selection`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save(&mut self, format: SaveFormat) {
         use std::path::Path;
-        let title = if json {
-            "Save Story to JSON"
-        } else {
-            "Export Story to Markdown"
-        };
-        let ext = if json { "json" } else { "md" };
+        let extensions = format.extensions();
         let mut dialog = egui_file::FileDialog::save_file(None)
-            .title(title)
+            .title(format.title())
             .show_files_filter(Box::new(move |path: &Path| {
-                path.extension().map_or(false, |e| e == ext)
+                path.extension()
+                    .is_some_and(|e| extensions.iter().any(|ext| e == *ext))
             }));
         dialog.open();
 
         // This will be displayed next frame. It's handled below in
         // `handle_save_dialog`.
-        self.saving_txt = !json;
+        self.save_format = format;
         self.save_dialog = Some(dialog);
     }
 
@@ -1218,34 +2328,8 @@ impl App {
             if let Some(path) = dialog.path() {
                 match dialog.dialog_type() {
                     egui_file::DialogType::OpenFile => {
-                        let text = match std::fs::read_to_string(path) {
-                            Ok(text) => text,
-                            Err(e) => {
-                                self.errors.push(
-                                    format!(
-                                        "Failed to read `{:?}` because: {}",
-                                        path, e
-                                    )
-                                    .into(),
-                                );
-                                return;
-                            }
-                        };
-                        let story: Story = match serde_json::from_str(&text) {
-                            Ok(story) => story,
-                            Err(e) => {
-                                self.errors.push(
-                                    format!(
-                                        "Failed to parse `{:?}` because: {}",
-                                        path, e
-                                    )
-                                    .into(),
-                                );
-                                return;
-                            }
-                        };
-
-                        self.stories.push(story);
+                        let path = path.to_path_buf();
+                        self.open(&path);
                     }
                     egui_file::DialogType::SaveFile => {
                         let active_story_index = match self.active_story {
@@ -1257,24 +2341,84 @@ impl App {
                             }
                         };
 
-                        let payload = if self.saving_txt {
-                            self.stories[active_story_index].to_string()
-                        } else {
-                            match serde_json::to_string(
-                                &self.stories[active_story_index],
-                            ) {
-                                Ok(json) => json,
-                                Err(e) => {
-                                    self.errors.push(format!(
-                                                "Failed to serialize stories because: {}",
-                                                e
-                                            ).into());
-                                    return;
+                        let story = &self.stories[active_story_index];
+                        let fingerprint = story.fingerprint();
+                        let payload = match self.save_format {
+                            SaveFormat::Markdown => {
+                                let mut markdown = String::new();
+                                story
+                                    .format_markdown(
+                                        &mut markdown,
+                                        self.settings.export_notes,
+                                    )
+                                    .unwrap();
+                                Ok(markdown)
+                            }
+                            SaveFormat::Json => serde_json::to_string(story),
+                            SaveFormat::Attribution => {
+                                let report =
+                                    crate::attribution::Report::new(story);
+                                if path
+                                    .extension()
+                                    .is_some_and(|ext| ext == "json")
+                                {
+                                    serde_json::to_string_pretty(&report)
+                                } else {
+                                    Ok(report.to_string())
                                 }
                             }
+                            SaveFormat::Dataset => {
+                                // Prompts are built exactly as for generation.
+                                let options = crate::dataset::Options {
+                                    include_authors: self
+                                        .settings
+                                        .prompt_include_authors,
+                                    include_title: self
+                                        .settings
+                                        .prompt_include_title,
+                                    ..self.settings.dataset.clone()
+                                };
+                                options.export(story).map(|(jsonl, _)| jsonl)
+                            }
+                            SaveFormat::Selection => {
+                                let mut markdown = String::new();
+                                story
+                                    .format_selected_markdown(
+                                        &mut markdown,
+                                        self.settings.export_notes,
+                                    )
+                                    .unwrap();
+                                Ok(markdown)
+                            }
+                        };
+                        let payload = match payload {
+                            Ok(payload) => payload,
+                            Err(e) => {
+                                self.errors.push(
+                                    format!(
+                                        "Failed to serialize story because: {}",
+                                        e
+                                    )
+                                    .into(),
+                                );
+                                return;
+                            }
                         };
 
-                        match std::fs::write(path, payload) {
+                        match crate::library::write_atomic(
+                            path,
+                            payload.as_bytes(),
+                        ) {
+                            Ok(_) if self.save_format == SaveFormat::Json => {
+                                // The story is now saved in place here.
+                                let story =
+                                    &mut self.stories[active_story_index];
+                                story.set_path(path);
+                                story.mark_saved(fingerprint);
+                                if let Some(path) = story.path() {
+                                    self.settings.add_recent_file(path);
+                                }
+                            }
                             Ok(_) => {}
                             Err(e) => {
                                 self.errors.push(
@@ -1301,7 +2445,7 @@ impl App {
 
     /// Draw clipboard.
     pub fn draw_clipboard(&mut self, ctx: &egui::Context) {
-        if let Some(node) = &self.node_clipboard {
+        if let Some(Clipping { node, .. }) = &self.node_clipboard {
             egui::TopBottomPanel::bottom("clipboard").show(ctx, |ui| {
                 let mut text =
                     node.to_string().chars().take(20).collect::<String>();
@@ -1317,112 +2461,675 @@ impl App {
     pub fn draw_toolbar(&mut self, ctx: &egui::Context) {
         if self.active_story.is_some() {
             egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
-                ui.horizontal(|ui| self.settings.layout.ui(ui));
+                ui.horizontal(|ui| {
+                    self.settings.layout.ui(ui);
+                    if ui
+                        .button("Unpin all")
+                        .on_hover_text_at_pointer(
+                            "Let the layout move nodes that were dragged into place.",
+                        )
+                        .clicked()
+                    {
+                        if let Some(story) = self.story_mut() {
+                            if story.unpin_all() > 0 {
+                                story.check_dirty();
+                            }
+                        }
+                    }
+                    ui.separator();
+                    if ui
+                        .add_enabled(
+                            !self.generation_ui_locked,
+                            egui::Button::new("Branch to new story"),
+                        )
+                        .on_hover_text_at_pointer(
+                            "Copy the active path, and everything below the selected node, to a new story.",
+                        )
+                        .clicked()
+                    {
+                        self.branch_to_new_story();
+                    }
+                    let can_undo_move =
+                        self.story().is_some_and(Story::can_undo_move);
+                    if ui
+                        .add_enabled(
+                            !self.generation_ui_locked && can_undo_move,
+                            egui::Button::new("Undo move"),
+                        )
+                        .on_hover_text_at_pointer(
+                            self.hint(ui.ctx(), Command::UndoMove),
+                        )
+                        .clicked()
+                    {
+                        self.run_command(Command::UndoMove);
+                    }
+                    ui.separator();
+                    self.draw_history_buttons(ui);
+                    ui.separator();
+                    self.draw_zoom_buttons(ui);
+                    ui.separator();
+                    self.draw_ratings_menu(ui);
+                    self.draw_selection_menu(ui);
+                    ui.separator();
+                    self.draw_tag_filter(ui);
+                });
             });
         }
     }
 
-    /// Handle input events (keyboard shortcuts, etc).
-    pub fn handle_input(
-        &mut self,
-        ctx: &eframe::egui::Context,
-        _frame: &mut eframe::Frame,
-    ) {
-        ctx.input(|input| {
-            // Command + key shortcuts
-            if input.modifiers.command && !input.modifiers.shift {
-                // Command + N: New empty paragraph with the default author.
-                // this code ensures that the author exists first because in our
-                // API, a panic will occur if the author does not exist. (We
-                // will probably change this in the future.)
-                if !self.generation_ui_locked && input.key_pressed(egui::Key::N)
-                {
-                    let author = self.settings.default_author.clone();
-                    if let Some(story) = self.story_mut() {
-                        let id = story.add_author(author);
-                        story.add_empty_paragraph(id);
+    /// Items for the command palette, depending on the argument being chosen.
+    fn palette_items(&self, ctx: &egui::Context) -> Vec<Item> {
+        let mut items = Vec::new();
+        match self.palette.argument {
+            None => {
+                for command in Command::ALL {
+                    // Bookmarks have their own entry, and the palette is
+                    // already open.
+                    if matches!(
+                        command,
+                        Command::GoToBookmark(_) | Command::Palette
+                    ) {
+                        continue;
                     }
+                    items.push(Item {
+                        label: command.to_string(),
+                        shortcut: self
+                            .settings
+                            .keybindings
+                            .shortcuts(command)
+                            .next()
+                            .map(|shortcut| ctx.format_shortcut(shortcut)),
+                        target: Target::Command(command),
+                    });
                 }
-                // Command + S: Save story to JSON.
-                #[cfg(not(target_arch = "wasm32"))]
-                if !self.generation_ui_locked
-                    && self.active_story.is_some()
-                    && input.key_pressed(egui::Key::S)
-                {
-                    self.save_to_json();
+                items.push(Item::new(
+                    "Switch to story…",
+                    Target::Choose(Argument::Story),
+                ));
+                items.push(Item::new(
+                    "Jump to bookmark…",
+                    Target::Choose(Argument::Bookmark),
+                ));
+                #[cfg(feature = "generate")]
+                if settings::GenerativeBackend::ALL.len() > 1 {
+                    items.push(Item::new(
+                        "Switch backend…",
+                        Target::Choose(Argument::Backend),
+                    ));
                 }
-                // Command + O: Load story from JSON.
-                #[cfg(not(target_arch = "wasm32"))]
-                if !self.generation_ui_locked && input.key_pressed(egui::Key::O)
-                {
-                    self.load_from_json();
+                #[allow(unused_variables)] // because it depends on features
+                let backend = self.settings.selected_generative_backend;
+                #[cfg(all(
+                    feature = "drama_llama",
+                    not(target_arch = "wasm32")
+                ))]
+                if backend == settings::GenerativeBackend::DramaLlama {
+                    items.push(Item::new("Load model…", Target::LoadModel));
                 }
-                // Command + DELETE: Delete selected node.
-                if !self.generation_ui_locked
-                    && input.key_pressed(egui::Key::Delete)
-                {
-                    if let Some(story) = self.story_mut() {
-                        story.decapitate();
+                #[cfg(feature = "openai")]
+                if backend == settings::GenerativeBackend::OpenAI {
+                    items.push(Item::new(
+                        "Fetch models",
+                        Target::Settings(settings::Action::OpenAI(
+                            crate::openai::SettingsAction::FetchModels,
+                        )),
+                    ));
+                }
+            }
+            Some(Argument::Story) => {
+                for (i, story) in self.stories.iter().enumerate() {
+                    items.push(Item::new(&story.title, Target::Story(i)));
+                }
+            }
+            Some(Argument::Bookmark) => {
+                if let Some(story) = self.story() {
+                    for (i, bookmark) in story.bookmarks().iter().enumerate() {
+                        let mut item =
+                            Item::new(&bookmark.name, Target::Bookmark(i));
+                        if let Some(shortcut) = self
+                            .settings
+                            .keybindings
+                            .shortcuts(Command::GoToBookmark(i as u8))
+                            .next()
+                        {
+                            item.shortcut = Some(ctx.format_shortcut(shortcut));
+                        }
+                        items.push(item);
                     }
                 }
-                // Command + ,: Cut selected node.
-                if !self.generation_ui_locked
-                    && input.key_pressed(egui::Key::Comma)
-                {
-                    if let Some(story) = self.story_mut() {
-                        self.node_clipboard = story.decapitate();
+            }
+            #[cfg(feature = "generate")]
+            Some(Argument::Backend) => {
+                let from = self.settings.selected_generative_backend;
+                for &&to in settings::GenerativeBackend::ALL {
+                    if to != from {
+                        items.push(Item::new(
+                            to.to_string(),
+                            Target::Settings(
+                                settings::Action::SwitchBackends { from, to },
+                            ),
+                        ));
                     }
                 }
-                // Command + .: Paste node from clipboard.
-                if !self.generation_ui_locked
-                    && input.key_pressed(egui::Key::Period)
-                {
-                    let node = self.node_clipboard.take();
-                    if let Some(story) = self.story_mut() {
-                        if let Some(node) = node {
-                            story.paste_node(node);
-                        }
-                    } else {
-                        // Put the node back. We do this because multiple
-                        // mutable references to self are not allowed.
-                        self.node_clipboard = node;
+            }
+        }
+        items
+    }
+
+    /// Draw the command palette, if open, and act on the chosen item.
+    fn draw_palette(&mut self, ctx: &egui::Context) {
+        if !self.palette.open {
+            return;
+        }
+        let items = self.palette_items(ctx);
+        match self.palette.ui(ctx, items) {
+            None | Some(Target::Choose(_)) => {}
+            Some(Target::Command(command)) => self.run_command(command),
+            Some(Target::Story(i)) => {
+                if i < self.stories.len() {
+                    self.active_story = Some(i);
+                    self.left_sidebar.editing_active_title = false;
+                    self.right_sidebar.refresh_story();
+                }
+            }
+            Some(Target::Bookmark(i)) => self.go_to_bookmark(i),
+            #[cfg(all(feature = "drama_llama", not(target_arch = "wasm32")))]
+            Some(Target::LoadModel) => {
+                // The dialog is drawn by the settings page.
+                self.settings.open_model_dialog();
+                self.left_sidebar.visible = true;
+                self.left_sidebar.page = SidebarPage::Settings;
+            }
+            Some(Target::Settings(action)) => {
+                if !self.generation_ui_locked {
+                    self.handle_settings_action(action, ctx);
+                }
+            }
+        }
+    }
+
+    /// A `command`'s description with its first shortcut, if any.
+    fn hint(&self, ctx: &egui::Context, command: Command) -> String {
+        match self.settings.keybindings.shortcuts(command).next() {
+            Some(shortcut) => {
+                format!("{} ({})", command, ctx.format_shortcut(shortcut))
+            }
+            None => command.to_string(),
+        }
+    }
+
+    /// Draw back and forward buttons for the active story.
+    fn draw_history_buttons(&mut self, ui: &mut egui::Ui) {
+        let Some(history) = self.story().map(|s| s.history().clone()) else {
+            return;
+        };
+        let unlocked = !self.generation_ui_locked;
+        if ui
+            .add_enabled(
+                unlocked && history.can_go_back(),
+                egui::Button::new("⏴"),
+            )
+            .on_hover_text_at_pointer(self.hint(ui.ctx(), Command::Back))
+            .clicked()
+        {
+            self.navigate_history(false);
+        }
+        if ui
+            .add_enabled(
+                unlocked && history.can_go_forward(),
+                egui::Button::new("⏵"),
+            )
+            .on_hover_text_at_pointer(self.hint(ui.ctx(), Command::Forward))
+            .clicked()
+        {
+            self.navigate_history(true);
+        }
+    }
+
+    /// Draw zoom buttons for the node view.
+    fn draw_zoom_buttons(&mut self, ui: &mut egui::Ui) {
+        for (text, command) in
+            [("Fit", Command::ZoomToFit), ("Head", Command::ZoomToHead)]
+        {
+            if ui
+                .button(text)
+                .on_hover_text_at_pointer(self.hint(ui.ctx(), command))
+                .clicked()
+            {
+                self.run_command(command);
+            }
+        }
+        ui.label(format!("{:.0}%", self.canvas.zoom() * 100.0));
+        let mut show_minimap = !self.settings.hide_minimap;
+        if ui
+            .checkbox(&mut show_minimap, "Minimap")
+            .on_hover_text_at_pointer(
+                "Show an overview of the whole story. Click it to pan.",
+            )
+            .changed()
+        {
+            self.settings.hide_minimap = !show_minimap;
+        }
+    }
+
+    /// Draw the tag filter for the node and tree views.
+    fn draw_tag_filter(&mut self, ui: &mut egui::Ui) {
+        let Some(story) = self.story() else {
+            return;
+        };
+        let tags: Vec<String> =
+            story.tags().into_iter().map(str::to_string).collect();
+        let selected = self.filter.tag.as_deref().unwrap_or("All nodes");
+        egui::ComboBox::from_label("Tag")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.filter.tag, None, "All nodes");
+                for tag in tags {
+                    let label = tag.clone();
+                    ui.selectable_value(&mut self.filter.tag, Some(tag), label);
+                }
+            });
+    }
+
+    /// Draw the filter above the tree view. The filter is shared with the node
+    /// view, where nodes that don't match are faded.
+    fn draw_tree_filter(&mut self, ui: &mut egui::Ui) {
+        let Some(story) = self.story() else {
+            return;
+        };
+        let authors: Vec<(AuthorIndex, String)> = story
+            .authors()
+            .map(|(id, name)| (id, name.to_string()))
+            .collect();
+        ui.add(
+            egui::TextEdit::singleline(&mut self.filter.text)
+                .hint_text("Filter by text"),
+        );
+        ui.horizontal(|ui| {
+            let selected = self
+                .filter
+                .author
+                .and_then(|id| authors.iter().find(|(i, _)| *i == id))
+                .map_or("Anyone", |(_, name)| name.as_str());
+            egui::ComboBox::from_label("Author")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(
+                        &mut self.filter.author,
+                        None,
+                        "Anyone",
+                    );
+                    for (id, name) in &authors {
+                        ui.selectable_value(
+                            &mut self.filter.author,
+                            Some(*id),
+                            name,
+                        );
                     }
+                });
+            let selected = self
+                .filter
+                .min_rating
+                .map_or("Any rating".to_string(), |r| format!("★{}+", r));
+            egui::ComboBox::from_label("Rating")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(
+                        &mut self.filter.min_rating,
+                        None,
+                        "Any rating",
+                    );
+                    for star in 1..=crate::node::MAX_RATING {
+                        ui.selectable_value(
+                            &mut self.filter.min_rating,
+                            Some(star),
+                            format!("★{}+", star),
+                        );
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            self.draw_tag_filter(ui);
+            if ui
+                .add_enabled(
+                    !self.filter.is_empty(),
+                    egui::Button::new("Clear"),
+                )
+                .clicked()
+            {
+                self.filter = Filter::default();
+            }
+        });
+        if ui
+            .button("Collapse all except active path")
+            .on_hover_text_at_pointer(
+                "Collapse every node that isn't on the active path.",
+            )
+            .clicked()
+        {
+            crate::node::collapse_tree(ui.ctx());
+        }
+        ui.separator();
+    }
+
+    /// Draw the ratings menu. Contains commands that act on node ratings and
+    /// flags.
+    fn draw_ratings_menu(&mut self, ui: &mut egui::Ui) {
+        let locked = self.generation_ui_locked;
+        ui.menu_button("Ratings", |ui| {
+            let mut modified = false;
+            if ui
+                .add_enabled(!locked, egui::Button::new("Follow best path"))
+                .on_hover_text_at_pointer(
+                    "From the root, select the highest rated child at each level.",
+                )
+                .clicked()
+            {
+                if let Some(story) = self.story_mut() {
+                    story.follow_best_path();
                 }
+                modified = true;
+                ui.close_menu();
             }
-            // Command + Shift + key shortcuts
-            if input.modifiers.command && input.modifiers.shift {
-                // Command + Shift + S: Export story to Markdown.
-                #[cfg(not(target_arch = "wasm32"))]
-                if !self.generation_ui_locked
-                    && self.active_story.is_some()
-                    && input.key_pressed(egui::Key::S)
-                {
-                    self.export_to_markdown();
+            ui.separator();
+            ui.add(
+                egui::Slider::new(
+                    &mut self.settings.prune_threshold,
+                    1..=crate::node::MAX_RATING,
+                )
+                .text("Threshold"),
+            );
+            let threshold = self.settings.prune_threshold;
+            if ui
+                .add_enabled(
+                    !locked,
+                    egui::Button::new(format!("Prune below ★{}", threshold)),
+                )
+                .on_hover_text_at_pointer(
+                    "Delete nodes rated below the threshold, and their children. Unrated nodes are kept.",
+                )
+                .clicked()
+            {
+                if let Some(story) = self.story_mut() {
+                    story.prune_below(threshold);
                 }
-                // Command + Shift + N: New story with the default author.
-                if !self.generation_ui_locked && input.key_pressed(egui::Key::N)
+                modified = true;
+                ui.close_menu();
+            }
+            if ui
+                .add_enabled(!locked, egui::Button::new("Prune rejected"))
+                .on_hover_text_at_pointer(
+                    "Delete nodes flagged as rejected, and their children.",
+                )
+                .clicked()
+            {
+                if let Some(story) = self.story_mut() {
+                    story.prune_rejected();
+                }
+                modified = true;
+                ui.close_menu();
+            }
+            if modified {
+                self.right_sidebar.refresh_story();
+            }
+        });
+    }
+
+    /// Draw the selection menu. Contains bulk operations on the nodes selected
+    /// by Shift or Command clicking them, or by Shift-dragging a lasso.
+    fn draw_selection_menu(&mut self, ui: &mut egui::Ui) {
+        let locked = self.generation_ui_locked;
+        let Some(story) = self.story() else {
+            return;
+        };
+        let count = story.selection_len();
+        let authors: Vec<(AuthorIndex, String)> = story
+            .authors()
+            .map(|(id, name)| (id, name.to_string()))
+            .collect();
+        let enabled = !locked && count > 0;
+        ui.menu_button(format!("Selection ({})", count), |ui| {
+            let mut modified = false;
+            if count == 0 {
+                ui.label(
+                    "Shift or Command click nodes, or Shift-drag on the background, to select them.",
+                );
+            }
+            ui.add_enabled_ui(enabled, |ui| {
+                ui.horizontal(|ui| {
+                    let edit = ui.add(
+                        egui::TextEdit::singleline(&mut self.selection_tag)
+                            .hint_text("tag")
+                            .desired_width(80.0),
+                    );
+                    let submit = edit.lost_focus()
+                        && ui.input(|input| input.key_pressed(egui::Key::Enter));
+                    if ui.button("Tag").clicked() || submit {
+                        let tag = std::mem::take(&mut self.selection_tag);
+                        if let Some(story) = self.story_mut() {
+                            story.tag_selected(&tag);
+                        }
+                        modified = true;
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Rate");
+                    for star in 1..=crate::node::MAX_RATING {
+                        if ui.button(format!("★{}", star)).clicked() {
+                            if let Some(story) = self.story_mut() {
+                                story.rate_selected(Some(star));
+                            }
+                            modified = true;
+                        }
+                    }
+                    if ui.button("Clear").clicked() {
+                        if let Some(story) = self.story_mut() {
+                            story.rate_selected(None);
+                        }
+                        modified = true;
+                    }
+                });
+                ui.menu_button("Set author", |ui| {
+                    for (id, name) in authors {
+                        if ui.button(name).clicked() {
+                            if let Some(story) = self.story_mut() {
+                                story.set_selected_author(id);
+                            }
+                            modified = true;
+                            ui.close_menu();
+                        }
+                    }
+                });
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui
+                    .button("Export branches")
+                    .on_hover_text_at_pointer(
+                        "Export the path to each selected node to Markdown.",
+                    )
+                    .clicked()
                 {
-                    let author = self.settings.default_author.clone();
-                    self.new_story("Untitled".to_string(), author);
+                    self.export_selection();
+                    ui.close_menu();
                 }
-                // Command + Shift + DELETE: Delete active story.
-                if !self.generation_ui_locked
-                    && input.key_pressed(egui::Key::Delete)
+                ui.separator();
+                if ui
+                    .button("Delete")
+                    .on_hover_text_at_pointer(
+                        "Delete the selected nodes and their children.",
+                    )
+                    .clicked()
                 {
-                    if let Some(i) = self.active_story {
-                        self.stories.remove(i);
-                        self.active_story = None;
+                    if let Some(story) = self.story_mut() {
+                        story.delete_selected();
                     }
+                    modified = true;
+                    ui.close_menu();
+                }
+            });
+            if ui
+                .add_enabled(count > 0, egui::Button::new("Clear selection"))
+                .clicked()
+            {
+                if let Some(story) = self.story_mut() {
+                    story.clear_selection();
                 }
+                ui.close_menu();
+            }
+            if modified {
+                self.right_sidebar.refresh_story();
             }
-            // Key shortcuts
-            if input.key_pressed(egui::Key::Escape) {
+        });
+    }
+
+    /// Handle input events (keyboard shortcuts, etc).
+    pub fn handle_input(
+        &mut self,
+        ctx: &eframe::egui::Context,
+        _frame: &mut eframe::Frame,
+    ) {
+        // The keybinding editor and the palette get the keys while they're
+        // open.
+        if self.settings.keybindings.is_recording(ctx.frame_nr())
+            || self.palette.open
+        {
+            return;
+        }
+        let typing = ctx.wants_keyboard_input();
+        let (mut commands, back, forward) = ctx.input(|input| {
+            (
+                self.settings.keybindings.pressed(input, typing),
+                input.pointer.button_pressed(egui::PointerButton::Extra1),
+                input.pointer.button_pressed(egui::PointerButton::Extra2),
+            )
+        });
+        // The mouse back and forward buttons aren't configurable.
+        if back {
+            commands.push(Command::Back);
+        }
+        if forward {
+            commands.push(Command::Forward);
+        }
+        for command in commands {
+            self.run_command(command);
+        }
+    }
+
+    /// Run a `command`, for example in response to a shortcut.
+    pub fn run_command(&mut self, command: Command) {
+        // Commands that only change the view can run during generation.
+        match command {
+            Command::ToggleLeftSidebar => {
                 self.left_sidebar.visible = !self.left_sidebar.visible;
+                return;
             }
-            if input.key_pressed(egui::Key::F1) {
+            Command::ToggleRightSidebar => {
                 self.right_sidebar.visible = !self.right_sidebar.visible;
+                return;
             }
-        });
+            Command::Bookmark => {
+                self.bookmark_head(String::new());
+                return;
+            }
+            Command::Palette => {
+                self.palette.show();
+                return;
+            }
+            Command::ZoomToFit => {
+                if let Some(story) = self.story() {
+                    let bounds = story.root().bounds();
+                    self.canvas.zoom_to(bounds);
+                }
+                return;
+            }
+            Command::ZoomToHead => {
+                if let Some(story) = self.story() {
+                    let head = story.head().meta.rect();
+                    self.canvas.zoom_to(head);
+                }
+                return;
+            }
+            _ => {}
+        }
+        if self.generation_ui_locked {
+            return;
+        }
+        match command {
+            Command::NewParagraph => {
+                // This ensures that the author exists first because in our
+                // API, a panic will occur if the author does not exist. (We
+                // will probably change this in the future.)
+                let author = self.settings.default_author.clone();
+                if let Some(story) = self.story_mut() {
+                    match story.add_author(author) {
+                        Some(id) => story.add_empty_paragraph(id),
+                        None => self.errors.push(
+                            "Too many authors in story.".to_string().into(),
+                        ),
+                    }
+                }
+            }
+            Command::NewStory => {
+                let author = self.settings.default_author.clone();
+                self.new_story("Untitled".to_string(), author);
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Save if self.active_story.is_some() => {
+                self.save_in_place()
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Load => self.load_from_json(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Command::Export if self.active_story.is_some() => {
+                self.export_to_markdown()
+            }
+            Command::DeleteNode => {
+                if let Some(story) = self.story_mut() {
+                    story.decapitate();
+                }
+            }
+            Command::DeleteStory => {
+                if let Some(i) = self.active_story {
+                    self.stories.remove(i);
+                    self.active_story = None;
+                }
+            }
+            Command::Cut => {
+                if let Some(story) = self.story_mut() {
+                    self.node_clipboard = story.cut_head();
+                }
+            }
+            Command::Paste => {
+                let clipping = self.node_clipboard.take();
+                if let Some(story) = self.story_mut() {
+                    if let Some(clipping) = clipping {
+                        story.paste(clipping);
+                    }
+                } else {
+                    // Put the node back. We do this because multiple
+                    // mutable references to self are not allowed.
+                    self.node_clipboard = clipping;
+                }
+            }
+            Command::UndoMove => {
+                if let Some(story) = self.story_mut() {
+                    story.undo_move();
+                }
+                self.right_sidebar.refresh_story();
+            }
+            Command::GoToBookmark(i) => self.go_to_bookmark(i as usize),
+            Command::Back => self.navigate_history(false),
+            Command::Forward => self.navigate_history(true),
+            Command::Parent => self.step(Step::Parent),
+            Command::Child => self.step(Step::Child),
+            Command::PreviousSibling => self.step(Step::PreviousSibling),
+            Command::NextSibling => self.step(Step::NextSibling),
+            Command::FirstSibling => self.step(Step::FirstSibling),
+            Command::Deepest => self.step(Step::Deepest),
+            Command::Generate => self.generate_at_head(false),
+            Command::Continue => self.generate_at_head(true),
+            #[allow(unreachable_patterns)] // because it depends on the target
+            _ => {}
+        }
     }
 }
 
@@ -1441,6 +3148,8 @@ impl eframe::App for App {
         #[cfg(not(target_arch = "wasm32"))]
         {
             self.handle_save_dialog(ctx);
+            self.autosave(ctx);
+            self.draw_recovery_prompt(ctx);
         }
         self.handle_input(ctx, frame);
         // handle any dialog that might be open
@@ -1449,9 +3158,27 @@ impl eframe::App for App {
         self.draw_toolbar(ctx);
         self.draw_clipboard(ctx);
         self.draw_central_panel(ctx, frame);
+        self.draw_palette(ctx);
     }
 
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(library) = &mut self.library {
+            // Once everything is in the library, the recovery copies aren't
+            // needed.
+            if let Err(e) = library
+                .save(&mut self.stories, &mut self.trash)
+                .and_then(|_| library.save_settings(&self.settings))
+                .and_then(|_| library.clear_recovery())
+            {
+                self.errors.push(
+                    format!("Failed to save the story library because: {}", e)
+                        .into(),
+                );
+            }
+            return;
+        }
+
         let serialized_stories = serde_json::to_string(&self.stories).unwrap();
         let serialized_settings =
             serde_json::to_string(&self.settings).unwrap();
@@ -1464,11 +3191,35 @@ impl eframe::App for App {
         storage.set_string("stories", serialized_stories);
         storage.set_string("settings", serialized_settings);
         storage.set_string("trash", serialized_trash);
+
+        // Stories without a file of their own are now saved.
+        for story in self.stories.iter_mut() {
+            #[cfg(not(target_arch = "wasm32"))]
+            if story.path().is_some() {
+                continue;
+            }
+            story.mark_saved(story.fingerprint());
+        }
     }
 
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
         if let Err(e) = self.shutdown_generative_backend() {
             eprintln!("Failed to cleanly shut down generative backend: {}", e);
         }
+        // The library was just saved. If that failed, the stories are left in
+        // the recovery directory and the session isn't ended so they're
+        // offered for recovery next time.
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(library) = &mut self.library {
+            match library.save_recovery(&mut self.stories) {
+                Ok(0) => {
+                    if let Err(e) = library.end_session() {
+                        eprintln!("Failed to end session: {}", e);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Failed to save recovery copies: {}", e),
+            }
+        }
     }
 }
@@ -0,0 +1,549 @@
+use egui::{Key, KeyboardShortcut, Modifiers};
+use serde::{Deserialize, Serialize};
+
+/// Something a keyboard shortcut does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Command {
+    NewParagraph,
+    NewStory,
+    Save,
+    Load,
+    Export,
+    DeleteNode,
+    DeleteStory,
+    Cut,
+    Paste,
+    UndoMove,
+    Bookmark,
+    /// Go to the bookmark at this index.
+    GoToBookmark(u8),
+    Back,
+    Forward,
+    Parent,
+    Child,
+    PreviousSibling,
+    NextSibling,
+    FirstSibling,
+    Deepest,
+    Generate,
+    Continue,
+    ToggleLeftSidebar,
+    ToggleRightSidebar,
+    Palette,
+    ZoomToFit,
+    ZoomToHead,
+}
+
+impl Command {
+    pub const ALL: [Self; 35] = [
+        Self::NewParagraph,
+        Self::NewStory,
+        Self::Save,
+        Self::Load,
+        Self::Export,
+        Self::DeleteNode,
+        Self::DeleteStory,
+        Self::Cut,
+        Self::Paste,
+        Self::UndoMove,
+        Self::Bookmark,
+        Self::GoToBookmark(0),
+        Self::GoToBookmark(1),
+        Self::GoToBookmark(2),
+        Self::GoToBookmark(3),
+        Self::GoToBookmark(4),
+        Self::GoToBookmark(5),
+        Self::GoToBookmark(6),
+        Self::GoToBookmark(7),
+        Self::GoToBookmark(8),
+        Self::Back,
+        Self::Forward,
+        Self::Parent,
+        Self::Child,
+        Self::PreviousSibling,
+        Self::NextSibling,
+        Self::FirstSibling,
+        Self::Deepest,
+        Self::Generate,
+        Self::Continue,
+        Self::ToggleLeftSidebar,
+        Self::ToggleRightSidebar,
+        Self::Palette,
+        Self::ZoomToFit,
+        Self::ZoomToHead,
+    ];
+}
+
+impl std::fmt::Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::NewParagraph => "New paragraph with the default author",
+            Self::NewStory => "New untitled story",
+            Self::Save => "Save story to JSON",
+            Self::Load => "Load story from JSON",
+            Self::Export => "Export story to Markdown/text",
+            Self::DeleteNode => "Delete the active node and all its children",
+            Self::DeleteStory => "Delete the active story",
+            Self::Cut => "Cut the active node and all its children",
+            Self::Paste => "Paste as a child of the active node",
+            Self::UndoMove => "Undo moving a node to a new parent",
+            Self::Bookmark => "Bookmark the active node",
+            Self::GoToBookmark(i) => {
+                return write!(f, "Go to bookmark {}", i + 1);
+            }
+            Self::Back => "Go back to the previously active node",
+            Self::Forward => "Go forward again",
+            Self::Parent => "Go to the parent",
+            Self::Child => "Go to the most recently visited child",
+            Self::PreviousSibling => "Go to the previous sibling",
+            Self::NextSibling => "Go to the next sibling",
+            Self::FirstSibling => "Go to the first sibling",
+            Self::Deepest => "Go to the most recently visited leaf",
+            Self::Generate => "Generate a new child of the active node",
+            Self::Continue => "Continue generating the active node",
+            Self::ToggleLeftSidebar => "Toggle stories and settings",
+            Self::ToggleRightSidebar => "Toggle the story text, tree and tools",
+            Self::Palette => "Open the command palette",
+            Self::ZoomToFit => "Zoom to fit the whole story",
+            Self::ZoomToHead => "Zoom to the active node",
+        };
+        f.write_str(s)
+    }
+}
+
+/// A [`KeyboardShortcut`] bound to a [`Command`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Binding {
+    pub command: Command,
+    pub shortcut: KeyboardShortcut,
+}
+
+/// Keyboard shortcuts. A command may have any number of them. Only the
+/// changes from the defaults are saved, so commands added later get their
+/// default shortcuts.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Overrides", into = "Overrides")]
+pub struct Keybindings {
+    bindings: Vec<Binding>,
+    /// The command waiting for a shortcut to be pressed in the editor.
+    recording: Option<Command>,
+    /// Frame the editor was last drawn in.
+    drawn: Option<u64>,
+}
+
+static_assertions::assert_impl_all!(Keybindings: Send, Sync);
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        const CMD: Modifiers = Modifiers::COMMAND;
+        const CMD_SHIFT: Modifiers = Modifiers {
+            shift: true,
+            ..Modifiers::COMMAND
+        };
+        const CMD_ALT: Modifiers = Modifiers {
+            alt: true,
+            ..Modifiers::COMMAND
+        };
+        const NONE: Modifiers = Modifiers::NONE;
+        const BOOKMARK_KEYS: [Key; 9] = [
+            Key::Num1,
+            Key::Num2,
+            Key::Num3,
+            Key::Num4,
+            Key::Num5,
+            Key::Num6,
+            Key::Num7,
+            Key::Num8,
+            Key::Num9,
+        ];
+
+        let mut bindings = vec![
+            (Command::NewParagraph, CMD, Key::N),
+            (Command::NewStory, CMD_SHIFT, Key::N),
+            (Command::Save, CMD, Key::S),
+            (Command::Load, CMD, Key::O),
+            (Command::Export, CMD_SHIFT, Key::S),
+            (Command::DeleteNode, CMD, Key::Delete),
+            (Command::DeleteStory, CMD_SHIFT, Key::Delete),
+            (Command::Cut, CMD, Key::Comma),
+            (Command::Paste, CMD, Key::Period),
+            (Command::UndoMove, CMD_ALT, Key::Z),
+            (Command::Bookmark, CMD, Key::B),
+            (Command::Back, CMD, Key::OpenBracket),
+            (Command::Back, Modifiers::ALT, Key::ArrowLeft),
+            (Command::Forward, CMD, Key::CloseBracket),
+            (Command::Forward, Modifiers::ALT, Key::ArrowRight),
+            (Command::Parent, NONE, Key::ArrowUp),
+            (Command::Child, NONE, Key::ArrowDown),
+            (Command::PreviousSibling, NONE, Key::ArrowLeft),
+            (Command::NextSibling, NONE, Key::ArrowRight),
+            (Command::FirstSibling, NONE, Key::Home),
+            (Command::Deepest, NONE, Key::End),
+            (Command::Generate, CMD, Key::Enter),
+            (Command::Continue, CMD_SHIFT, Key::Enter),
+            (Command::ToggleLeftSidebar, NONE, Key::Escape),
+            (Command::ToggleRightSidebar, NONE, Key::F1),
+            (Command::Palette, CMD_SHIFT, Key::P),
+            (Command::ZoomToFit, NONE, Key::F),
+            (Command::ZoomToHead, NONE, Key::H),
+        ];
+        for (i, key) in BOOKMARK_KEYS.into_iter().enumerate() {
+            bindings.push((Command::GoToBookmark(i as u8), CMD, key));
+        }
+
+        Self {
+            bindings: bindings
+                .into_iter()
+                .map(|(command, modifiers, key)| Binding {
+                    command,
+                    shortcut: KeyboardShortcut::new(modifiers, key),
+                })
+                .collect(),
+            recording: None,
+            drawn: None,
+        }
+    }
+}
+
+/// How [`Keybindings`] are saved: the bindings added to and removed from the
+/// defaults.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Overrides {
+    added: Vec<Binding>,
+    removed: Vec<Binding>,
+}
+
+impl From<Overrides> for Keybindings {
+    fn from(overrides: Overrides) -> Self {
+        let mut keybindings = Self::default();
+        keybindings
+            .bindings
+            .retain(|binding| !overrides.removed.contains(binding));
+        for Binding { command, shortcut } in overrides.added {
+            keybindings.bind(command, shortcut);
+        }
+        keybindings
+    }
+}
+
+impl From<Keybindings> for Overrides {
+    fn from(keybindings: Keybindings) -> Self {
+        let defaults = Keybindings::default().bindings;
+        Self {
+            added: keybindings
+                .bindings
+                .iter()
+                .filter(|binding| !defaults.contains(binding))
+                .copied()
+                .collect(),
+            removed: defaults
+                .into_iter()
+                .filter(|binding| !keybindings.bindings.contains(binding))
+                .collect(),
+        }
+    }
+}
+
+impl Keybindings {
+    /// Shortcuts bound to a `command`.
+    pub fn shortcuts(
+        &self,
+        command: Command,
+    ) -> impl Iterator<Item = &KeyboardShortcut> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |b| b.command == command)
+            .map(|b| &b.shortcut)
+    }
+
+    /// Bind a `shortcut` to a `command`. Returns false if it already is.
+    pub fn bind(
+        &mut self,
+        command: Command,
+        shortcut: KeyboardShortcut,
+    ) -> bool {
+        let binding = Binding { command, shortcut };
+        if self.bindings.contains(&binding) {
+            false
+        } else {
+            self.bindings.push(binding);
+            true
+        }
+    }
+
+    /// Unbind a `shortcut` from a `command`.
+    pub fn unbind(&mut self, command: Command, shortcut: KeyboardShortcut) {
+        self.bindings
+            .retain(|b| b.command != command || b.shortcut != shortcut);
+    }
+
+    /// Shortcuts bound to more than one command, with those commands.
+    pub fn conflicts(&self) -> Vec<(KeyboardShortcut, Vec<Command>)> {
+        let mut conflicts: Vec<(KeyboardShortcut, Vec<Command>)> = Vec::new();
+        for binding in self.bindings.iter() {
+            match conflicts.iter_mut().find(|(s, _)| *s == binding.shortcut) {
+                Some((_, commands)) => {
+                    if !commands.contains(&binding.command) {
+                        commands.push(binding.command)
+                    }
+                }
+                None => {
+                    conflicts.push((binding.shortcut, vec![binding.command]))
+                }
+            }
+        }
+        conflicts.retain(|(_, commands)| commands.len() > 1);
+        conflicts
+    }
+
+    /// Returns true if the editor is waiting for a shortcut to be pressed, in
+    /// which case shortcuts shouldn't run. If the editor wasn't drawn in the
+    /// frame before `frame_nr`, it was hidden, so recording is cancelled.
+    pub fn is_recording(&mut self, frame_nr: u64) -> bool {
+        if self.drawn.is_some_and(|drawn| drawn + 1 < frame_nr) {
+            self.recording = None;
+        }
+        self.recording.is_some()
+    }
+
+    /// Commands with a shortcut pressed this frame. If `typing` in a text
+    /// field, shortcuts that might edit text are ignored.
+    pub fn pressed(
+        &self,
+        input: &egui::InputState,
+        typing: bool,
+    ) -> Vec<Command> {
+        let mut commands = Vec::new();
+        for Binding { command, shortcut } in self.bindings.iter() {
+            if input.modifiers.matches_exact(shortcut.modifiers)
+                && input.key_pressed(shortcut.logical_key)
+                && (!typing || works_while_typing(shortcut))
+                && !commands.contains(command)
+            {
+                commands.push(*command);
+            }
+        }
+        commands
+    }
+
+    /// Markdown help listing every command that has a shortcut. Shortcuts are
+    /// formatted with `format`, for example [`egui::Context::format_shortcut`].
+    pub fn help(&self, format: impl Fn(&KeyboardShortcut) -> String) -> String {
+        let mut help = "## Keyboard shortcuts:\n\n".to_string();
+        for command in Command::ALL {
+            let shortcuts: Vec<String> = self
+                .shortcuts(command)
+                .map(|s| format!("`{}`", format(s)))
+                .collect();
+            if !shortcuts.is_empty() {
+                help.push_str(&format!(
+                    "- {} {}.\n",
+                    shortcuts.join(" or "),
+                    command
+                ));
+            }
+        }
+        help.push_str(
+            "\nThe mouse back and forward buttons also go back and forward.\n",
+        );
+        help
+    }
+
+    /// Draw the keybinding editor. While recording, Escape cancels.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        self.drawn = Some(ui.ctx().frame_nr());
+        if let Some(command) = self.recording {
+            let pressed = ui.input(|input| {
+                input.events.iter().find_map(|event| match event {
+                    egui::Event::Key {
+                        key,
+                        pressed: true,
+                        modifiers,
+                        ..
+                    } => Some(KeyboardShortcut::new(*modifiers, *key)),
+                    _ => None,
+                })
+            });
+            if let Some(shortcut) = pressed {
+                if shortcut
+                    != KeyboardShortcut::new(Modifiers::NONE, Key::Escape)
+                {
+                    self.bind(command, shortcut);
+                }
+                self.recording = None;
+            }
+        }
+
+        let conflicts = self.conflicts();
+        let mut unbind = None;
+        egui::Grid::new("keybindings").striped(true).show(ui, |ui| {
+            for command in Command::ALL {
+                ui.label(command.to_string());
+                ui.horizontal_wrapped(|ui| {
+                    for shortcut in self.shortcuts(command) {
+                        let mut text = egui::RichText::new(
+                            ui.ctx().format_shortcut(shortcut),
+                        );
+                        if conflicts.iter().any(|(s, _)| s == shortcut) {
+                            text = text.color(ui.visuals().error_fg_color);
+                        }
+                        if ui
+                            .button(text)
+                            .on_hover_text_at_pointer("Remove this shortcut.")
+                            .clicked()
+                        {
+                            unbind = Some((command, *shortcut));
+                        }
+                    }
+                    if self.recording == Some(command) {
+                        if ui
+                            .button("Press a shortcut…")
+                            .on_hover_text_at_pointer(
+                                "Press Escape or click to cancel.",
+                            )
+                            .clicked()
+                        {
+                            self.recording = None;
+                        }
+                    } else if ui
+                        .button("+")
+                        .on_hover_text_at_pointer("Add a shortcut.")
+                        .clicked()
+                    {
+                        self.recording = Some(command);
+                    }
+                });
+                ui.end_row();
+            }
+        });
+        if let Some((command, shortcut)) = unbind {
+            self.unbind(command, shortcut);
+        }
+
+        for (shortcut, commands) in conflicts {
+            let commands: Vec<String> =
+                commands.iter().map(Command::to_string).collect();
+            ui.colored_label(
+                ui.visuals().error_fg_color,
+                format!(
+                    "{} is bound to: {}.",
+                    ui.ctx().format_shortcut(&shortcut),
+                    commands.join("; ")
+                ),
+            );
+        }
+
+        if ui.button("Reset to defaults").clicked() {
+            *self = Self::default();
+        }
+    }
+}
+
+/// Whether a `shortcut` should work while a text field has focus. Those without
+/// Command or Ctrl would edit the text instead, apart from Escape and function
+/// keys.
+fn works_while_typing(shortcut: &KeyboardShortcut) -> bool {
+    let Modifiers {
+        command,
+        ctrl,
+        mac_cmd,
+        ..
+    } = shortcut.modifiers;
+    command
+        || ctrl
+        || mac_cmd
+        || shortcut.logical_key == Key::Escape
+        || shortcut
+            .logical_key
+            .name()
+            .strip_prefix('F')
+            .is_some_and(|n| n.parse::<u8>().is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let bindings = Keybindings::default();
+        assert!(bindings.conflicts().is_empty());
+        assert_eq!(bindings.shortcuts(Command::Back).count(), 2);
+        for command in Command::ALL {
+            assert!(
+                bindings.shortcuts(command).next().is_some(),
+                "{command:?} has no default shortcut"
+            );
+        }
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let mut bindings = Keybindings::default();
+        let save = KeyboardShortcut::new(Modifiers::COMMAND, Key::S);
+        assert!(!bindings.bind(Command::Save, save));
+        assert!(bindings.bind(Command::Load, save));
+        assert_eq!(
+            bindings.conflicts(),
+            vec![(save, vec![Command::Save, Command::Load])]
+        );
+        bindings.unbind(Command::Save, save);
+        assert!(bindings.conflicts().is_empty());
+        assert_eq!(bindings.shortcuts(Command::Save).count(), 0);
+    }
+
+    #[test]
+    fn test_serde() {
+        let mut bindings = Keybindings::default();
+        let save = KeyboardShortcut::new(Modifiers::COMMAND, Key::S);
+        let load = KeyboardShortcut::new(Modifiers::ALT, Key::L);
+        bindings.unbind(Command::Save, save);
+        bindings.bind(Command::Load, load);
+        let json = serde_json::to_string(&bindings).unwrap();
+        let loaded: Keybindings = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, bindings);
+
+        // Commands missing from what was saved get their defaults.
+        let loaded: Keybindings = serde_json::from_str("{}").unwrap();
+        assert_eq!(loaded, Keybindings::default());
+    }
+
+    #[test]
+    fn test_recording() {
+        let mut bindings = Keybindings {
+            recording: Some(Command::Save),
+            drawn: Some(1),
+            ..Keybindings::default()
+        };
+        assert!(bindings.is_recording(2));
+        // The editor wasn't drawn in frame 2, so it was hidden.
+        assert!(!bindings.is_recording(3));
+    }
+
+    #[test]
+    fn test_help() {
+        let mut bindings = Keybindings::default();
+        bindings.unbind(
+            Command::Save,
+            KeyboardShortcut::new(Modifiers::COMMAND, Key::S),
+        );
+        let help = bindings.help(|s| s.logical_key.name().to_string());
+        assert!(help.contains("- `N` New paragraph with the default author.\n"));
+        assert!(help.contains("- `OpenBracket` or `Left` Go back"));
+        assert!(!help.contains("Save story"));
+    }
+
+    #[test]
+    fn test_works_while_typing() {
+        let shortcut = |modifiers, key| KeyboardShortcut::new(modifiers, key);
+        assert!(works_while_typing(&shortcut(Modifiers::COMMAND, Key::N)));
+        assert!(works_while_typing(&shortcut(Modifiers::NONE, Key::F1)));
+        assert!(works_while_typing(&shortcut(Modifiers::NONE, Key::Escape)));
+        assert!(!works_while_typing(&shortcut(Modifiers::NONE, Key::F)));
+        assert!(!works_while_typing(&shortcut(
+            Modifiers::ALT,
+            Key::ArrowLeft
+        )));
+    }
+}
@@ -0,0 +1,215 @@
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+
+use super::{keybindings::Command, settings};
+
+/// An argument to choose before acting on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Argument {
+    Story,
+    Bookmark,
+    #[cfg(feature = "generate")]
+    Backend,
+}
+
+/// What choosing an [`Item`] does.
+pub enum Target {
+    Command(Command),
+    /// List the choices for an argument.
+    Choose(Argument),
+    /// Switch to the story at this index.
+    Story(usize),
+    /// Go to the bookmark at this index in the active story.
+    Bookmark(usize),
+    /// Open the model file dialog.
+    #[cfg(all(feature = "drama_llama", not(target_arch = "wasm32")))]
+    LoadModel,
+    Settings(settings::Action),
+}
+
+/// An entry in the [`Palette`].
+pub struct Item {
+    pub label: String,
+    /// Formatted keyboard shortcut, if any.
+    pub shortcut: Option<String>,
+    pub target: Target,
+}
+
+impl Item {
+    pub fn new(label: impl Into<String>, target: Target) -> Self {
+        Self {
+            label: label.into(),
+            shortcut: None,
+            target,
+        }
+    }
+}
+
+/// Fuzzy-searchable list of everything the app can do.
+#[derive(Default)]
+pub struct Palette {
+    /// Whether the palette is shown.
+    pub open: bool,
+    /// The argument being chosen, if any. Otherwise, actions are listed.
+    pub argument: Option<Argument>,
+    query: String,
+    /// Index into the filtered items.
+    selected: usize,
+}
+
+impl Palette {
+    /// Show the palette, listing actions.
+    pub fn show(&mut self) {
+        self.open = true;
+        self.choose(None);
+    }
+
+    /// List choices for an `argument`, or actions if None.
+    pub fn choose(&mut self, argument: Option<Argument>) {
+        self.argument = argument;
+        self.query.clear();
+        self.selected = 0;
+    }
+
+    /// Draw the palette, if open, listing `items`. Returns the chosen target.
+    /// The palette closes unless the target is [`Target::Choose`].
+    pub fn ui(
+        &mut self,
+        ctx: &egui::Context,
+        items: Vec<Item>,
+    ) -> Option<Target> {
+        if !self.open {
+            return None;
+        }
+
+        let ranked = rank(&items, &self.query);
+        let (up, down, enter, escape) = ctx.input(|input| {
+            (
+                input.key_pressed(egui::Key::ArrowUp),
+                input.key_pressed(egui::Key::ArrowDown),
+                input.key_pressed(egui::Key::Enter),
+                input.key_pressed(egui::Key::Escape),
+            )
+        });
+        if down {
+            self.selected += 1;
+        }
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        self.selected = self.selected.min(ranked.len().saturating_sub(1));
+
+        let mut chosen = enter.then(|| ranked.get(self.selected)).flatten();
+        egui::Window::new("Command palette")
+            .collapsible(false)
+            .resizable(false)
+            .title_bar(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 48.0])
+            .show(ctx, |ui| {
+                ui.set_width(400.0);
+                let hint = match self.argument {
+                    None => "Type a command…",
+                    Some(Argument::Story) => "Switch to story…",
+                    Some(Argument::Bookmark) => "Jump to bookmark…",
+                    #[cfg(feature = "generate")]
+                    Some(Argument::Backend) => "Switch backend…",
+                };
+                let query = ui.add(
+                    egui::TextEdit::singleline(&mut self.query)
+                        .hint_text(hint)
+                        .desired_width(f32::INFINITY),
+                );
+                query.request_focus();
+                if query.changed() {
+                    self.selected = 0;
+                }
+
+                egui::ScrollArea::vertical()
+                    .max_height(320.0)
+                    .show(ui, |ui| {
+                        for (row, &i) in ranked.iter().enumerate() {
+                            let item = &items[i];
+                            let resp = ui
+                                .horizontal(|ui| {
+                                    let resp = ui.selectable_label(
+                                        row == self.selected,
+                                        &item.label,
+                                    );
+                                    if let Some(shortcut) = &item.shortcut {
+                                        ui.with_layout(
+                                            egui::Layout::right_to_left(
+                                                egui::Align::Center,
+                                            ),
+                                            |ui| ui.weak(shortcut),
+                                        );
+                                    }
+                                    resp
+                                })
+                                .inner;
+                            if row == self.selected && (up || down) {
+                                resp.scroll_to_me(None);
+                            }
+                            if resp.clicked() {
+                                chosen = Some(&ranked[row]);
+                            }
+                        }
+                        if ranked.is_empty() {
+                            ui.weak("No matches.");
+                        }
+                    });
+            });
+
+        if escape {
+            self.open = false;
+            return None;
+        }
+        let chosen = *chosen?;
+        let target = items.into_iter().nth(chosen)?.target;
+        match target {
+            Target::Choose(argument) => self.choose(Some(argument)),
+            _ => self.open = false,
+        }
+        Some(target)
+    }
+}
+
+/// Indices of the `items` that match a fuzzy `query`, best first. Ties keep
+/// their order. All items match an empty query.
+pub fn rank(items: &[Item], query: &str) -> Vec<usize> {
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut scored: Vec<(i64, usize)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| {
+            matcher
+                .fuzzy_match(&item.label, query)
+                .map(|score| (score, i))
+        })
+        .collect();
+    // Sorting is stable so equal scores stay in order.
+    scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank() {
+        let items: Vec<Item> = [
+            Command::Save,
+            Command::NewStory,
+            Command::Export,
+            Command::DeleteStory,
+        ]
+        .into_iter()
+        .map(|command| Item::new(command.to_string(), Target::Command(command)))
+        .collect();
+
+        assert_eq!(rank(&items, ""), vec![0, 1, 2, 3]);
+        assert_eq!(rank(&items, "new story").first(), Some(&1));
+        assert_eq!(rank(&items, "expmd"), vec![2]);
+        assert_eq!(rank(&items, "DEL").first(), Some(&3));
+        assert!(rank(&items, "xyzzy").is_empty());
+    }
+}
@@ -186,7 +186,7 @@ impl Into<std::path::PathBuf> for &mut BackendOptions {
 
 /// Crate settings.
 // This is used for App but not much else so we might feature gate this to `gui`
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Settings {
     /// Default author for new nodes.
     pub default_author: String,
@@ -196,6 +196,24 @@ pub struct Settings {
     pub prompt_include_title: bool,
     /// Node layout settings.
     pub layout: Layout,
+    /// Nodes rated below this are removed when pruning.
+    #[serde(default = "Settings::default_prune_threshold")]
+    pub prune_threshold: u8,
+    /// Whether node notes are included in Markdown exports.
+    #[serde(default)]
+    pub export_notes: bool,
+    /// Whether the node view's minimap is hidden.
+    #[serde(default)]
+    pub hide_minimap: bool,
+    /// Story files recently opened or saved, most recent first.
+    #[serde(default)]
+    pub recent_files: Vec<PathBuf>,
+    /// Fine-tuning dataset export options.
+    #[serde(default)]
+    pub dataset: crate::dataset::Options,
+    /// Keyboard shortcuts.
+    #[serde(default)]
+    pub keybindings: super::keybindings::Keybindings,
     #[cfg(feature = "generate")]
     #[serde(default)]
     pub selected_generative_backend: GenerativeBackend,
@@ -215,6 +233,30 @@ pub struct Settings {
     pub loading_model: Option<PathBuf>,
 }
 
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            default_author: String::new(),
+            prompt_include_authors: false,
+            prompt_include_title: false,
+            layout: Layout::default(),
+            prune_threshold: Self::default_prune_threshold(),
+            export_notes: false,
+            hide_minimap: false,
+            recent_files: Vec::new(),
+            dataset: Default::default(),
+            keybindings: Default::default(),
+            #[cfg(feature = "generate")]
+            selected_generative_backend: Default::default(),
+            #[cfg(feature = "generate")]
+            backend_options: Default::default(),
+            pending_backend_switch: None,
+            #[cfg(all(feature = "drama_llama", not(target_arch = "wasm32")))]
+            loading_model: None,
+        }
+    }
+}
+
 pub(crate) enum Action {
     /// The user has requested to switch generative backends. When the switch is
     /// complete, `Settings::pending_backend_switch` should be set to `None`.
@@ -231,6 +273,23 @@ pub(crate) enum Action {
 }
 
 impl Settings {
+    /// Default [`Settings::prune_threshold`], the middle rating.
+    pub fn default_prune_threshold() -> u8 {
+        3
+    }
+
+    /// Most files kept in [`Settings::recent_files`].
+    pub const RECENT_FILES_LIMIT: usize = 10;
+
+    /// Move `path` to the front of the recent files, dropping the oldest if
+    /// there are too many.
+    pub fn add_recent_file(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        self.recent_files.retain(|p| *p != path);
+        self.recent_files.insert(0, path);
+        self.recent_files.truncate(Self::RECENT_FILES_LIMIT);
+    }
+
     #[cfg(feature = "generate")]
     pub fn backend_options(&mut self) -> &mut BackendOptions {
         self.backend_options
@@ -352,18 +411,7 @@ impl Settings {
                 }
 
                 if ui.button("Load Model").clicked() {
-                    let filter = move |path: &std::path::Path| {
-                        path.extension().map_or(false, |ext| ext == "gguf")
-                    };
-                    let start = if model.as_os_str().is_empty() {
-                        None
-                    } else {
-                        Some(model.clone())
-                    };
-                    let mut dialog = egui_file::FileDialog::open_file(start)
-                        .show_files_filter(Box::new(filter));
-                    dialog.open();
-                    *file_dialog = Some(dialog);
+                    *file_dialog = Some(model_dialog(model));
                 }
 
                 if let Some(dialog) = file_dialog {
@@ -452,6 +500,10 @@ impl Settings {
         ui.label("Default author:");
         ui.text_edit_singleline(&mut self.default_author);
 
+        ui.separator();
+        egui::CollapsingHeader::new("Keyboard shortcuts")
+            .show(ui, |ui| self.keybindings.ui(ui));
+
         #[cfg(feature = "generate")]
         {
             ui.separator();
@@ -463,6 +515,18 @@ impl Settings {
         None
     }
 
+    /// Open the dialog to choose a model to load. The dialog is shown, and
+    /// the model loaded, by [`Settings::draw`].
+    #[cfg(all(feature = "drama_llama", not(target_arch = "wasm32")))]
+    pub fn open_model_dialog(&mut self) {
+        if let BackendOptions::DramaLlama {
+            model, file_dialog, ..
+        } = self.backend_options()
+        {
+            *file_dialog = Some(model_dialog(model));
+        }
+    }
+
     /// This should be called once on startup to configure the backend settings,
     /// for example, validating a local model or fetching a list of models from
     /// OpenAI.
@@ -500,3 +564,51 @@ impl Settings {
         }
     }
 }
+
+/// An open file dialog for choosing a GGUF model, starting at the current
+/// `model`, if any.
+#[cfg(all(feature = "drama_llama", not(target_arch = "wasm32")))]
+fn model_dialog(model: &std::path::Path) -> egui_file::FileDialog {
+    let filter = move |path: &std::path::Path| {
+        path.extension().map_or(false, |ext| ext == "gguf")
+    };
+    let start = if model.as_os_str().is_empty() {
+        None
+    } else {
+        Some(model.to_path_buf())
+    };
+    let mut dialog = egui_file::FileDialog::open_file(start)
+        .show_files_filter(Box::new(filter));
+    dialog.open();
+    dialog
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_files() {
+        let mut settings = Settings::default();
+        for i in 0..=Settings::RECENT_FILES_LIMIT {
+            settings.add_recent_file(format!("{}.json", i));
+        }
+        assert_eq!(settings.recent_files.len(), Settings::RECENT_FILES_LIMIT);
+        assert_eq!(settings.recent_files[0], PathBuf::from("10.json"));
+        // Reopening a file moves it to the front rather than repeating it.
+        settings.add_recent_file("5.json");
+        assert_eq!(settings.recent_files.len(), Settings::RECENT_FILES_LIMIT);
+        assert_eq!(settings.recent_files[0], PathBuf::from("5.json"));
+        assert_eq!(settings.recent_files[1], PathBuf::from("10.json"));
+    }
+
+    #[test]
+    fn test_prune_threshold() {
+        // Settings saved before the threshold existed get the same default.
+        let mut json = serde_json::to_value(Settings::default()).unwrap();
+        json.as_object_mut().unwrap().remove("prune_threshold");
+        let settings: Settings = serde_json::from_value(json).unwrap();
+        assert_eq!(settings.prune_threshold, 3);
+        assert_eq!(Settings::default().prune_threshold, 3);
+    }
+}
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+
+use crate::story::{AuthorRole, Story};
+
+/// A paragraph of the active path along with who wrote it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Paragraph {
+    /// Index into [`Report::contributors`].
+    pub contributor: usize,
+    /// The text of the paragraph.
+    pub text: String,
+}
+
+/// An author's share of the active path.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Contribution {
+    /// Author name.
+    pub author: String,
+    /// Whether the author is a person or a model.
+    pub role: AuthorRole,
+    /// Number of paragraphs written by the author.
+    pub paragraphs: usize,
+    /// Number of characters written by the author.
+    pub chars: usize,
+    /// Percentage of all characters written by the author.
+    pub percent: f32,
+}
+
+/// Who wrote what along a [`Story`]'s active path. This is suitable for
+/// disclosure of generated content.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Report {
+    /// Story title.
+    pub title: String,
+    /// Authors of the active path, in order of first appearance.
+    pub contributors: Vec<Contribution>,
+    /// Paragraphs of the active path, in order. Empty nodes are skipped.
+    pub paragraphs: Vec<Paragraph>,
+    /// Percentage of all characters written by people.
+    pub human_percent: f32,
+}
+
+static_assertions::assert_impl_all!(Report: Send, Sync);
+
+impl Report {
+    /// Build a report for the active path of a `story`.
+    pub fn new(story: &Story) -> Self {
+        let title = if story.title.is_empty() {
+            crate::consts::DEFAULT_TITLE.to_string()
+        } else {
+            story.title.clone()
+        };
+
+        // Author id to index in `contributors`.
+        let mut index = std::collections::HashMap::new();
+        let mut contributors: Vec<Contribution> = Vec::new();
+        let mut paragraphs = Vec::new();
+
+        for node in story.iter_active_path() {
+            let text = node.to_string();
+            if text.trim().is_empty() {
+                continue;
+            }
+
+            let contributor =
+                *index.entry(node.author_id).or_insert_with(|| {
+                    let profile = story.author_profile(node.author_id);
+                    contributors.push(Contribution {
+                        author: profile.display_name,
+                        role: profile.role,
+                        paragraphs: 0,
+                        chars: 0,
+                        percent: 0.0,
+                    });
+                    contributors.len() - 1
+                });
+
+            let contribution = &mut contributors[contributor];
+            contribution.paragraphs += 1;
+            contribution.chars += text.chars().count();
+
+            paragraphs.push(Paragraph { contributor, text });
+        }
+
+        let total: usize = contributors.iter().map(|c| c.chars).sum();
+        let mut human_percent = 0.0;
+        if total > 0 {
+            for contribution in contributors.iter_mut() {
+                contribution.percent =
+                    contribution.chars as f32 / total as f32 * 100.0;
+                if contribution.role == AuthorRole::Human {
+                    human_percent += contribution.percent;
+                }
+            }
+        }
+
+        Self {
+            title,
+            contributors,
+            paragraphs,
+            human_percent,
+        }
+    }
+
+    /// Percentage of all characters written by models.
+    pub fn model_percent(&self) -> f32 {
+        if self.contributors.is_empty() {
+            0.0
+        } else {
+            100.0 - self.human_percent
+        }
+    }
+
+    /// Format the report as Markdown. Each paragraph is followed by a footnote
+    /// marker crediting its author, and a summary table follows the text.
+    pub fn format_markdown<F>(&self, mut f: F) -> std::fmt::Result
+    where
+        F: std::fmt::Write,
+    {
+        writeln!(f, "# {}", self.title)?;
+
+        for paragraph in self.paragraphs.iter() {
+            writeln!(
+                f,
+                "\n{}[^{}]",
+                paragraph.text.trim_end(),
+                paragraph.contributor + 1
+            )?;
+        }
+
+        writeln!(f, "\n---\n\n## Contributions\n")?;
+        writeln!(f, "| Author | Role | Paragraphs | Characters | Share |")?;
+        writeln!(f, "|---|---|---:|---:|---:|")?;
+        for c in self.contributors.iter() {
+            writeln!(
+                f,
+                "| {} | {} | {} | {} | {:.1}% |",
+                c.author.replace('|', "\\|"),
+                c.role.as_str(),
+                c.paragraphs,
+                c.chars,
+                c.percent
+            )?;
+        }
+        writeln!(
+            f,
+            "\nHuman: {:.1}%, Model: {:.1}%",
+            self.human_percent,
+            self.model_percent()
+        )?;
+
+        if !self.contributors.is_empty() {
+            writeln!(f)?;
+        }
+        for (i, c) in self.contributors.iter().enumerate() {
+            let verb = match c.role {
+                AuthorRole::Human => "Written",
+                AuthorRole::Model => "Generated",
+            };
+            writeln!(
+                f,
+                "[^{}]: {} by {} ({}).",
+                i + 1,
+                verb,
+                c.author,
+                c.role.as_str()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.format_markdown(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn story() -> Story {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["Hello", " World"]);
+        let model = story.add_model_author("gpt").unwrap();
+        story.add_paragraph(model, ["Goodbye", " World"]);
+        story.add_paragraph("Alice", ["Bye"]);
+        story
+    }
+
+    #[test]
+    fn test_report() {
+        let report = Report::new(&story());
+        assert_eq!(report.title, "Test");
+        assert_eq!(report.paragraphs.len(), 3);
+        assert_eq!(report.contributors.len(), 2);
+
+        let alice = &report.contributors[0];
+        assert_eq!(alice.author, "Alice");
+        assert_eq!(alice.role, AuthorRole::Human);
+        assert_eq!(alice.paragraphs, 2);
+        assert_eq!(alice.chars, 14);
+
+        let gpt = &report.contributors[1];
+        assert_eq!(gpt.author, "gpt");
+        assert_eq!(gpt.role, AuthorRole::Model);
+        assert_eq!(gpt.chars, 13);
+
+        assert!((report.human_percent - 14.0 / 27.0 * 100.0).abs() < 1e-3);
+        assert!((report.human_percent + gpt.percent - 100.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_report_markdown() {
+        let markdown = Report::new(&story()).to_string();
+        assert!(markdown.starts_with("# Test\n\nHello World[^1]\n"));
+        assert!(markdown.contains("\nGoodbye World[^2]\n"));
+        assert!(markdown.contains("| gpt | model | 1 | 13 | 48.1% |"));
+        assert!(markdown.contains("[^1]: Written by Alice (human)."));
+        assert!(markdown.contains("[^2]: Generated by gpt (model)."));
+    }
+
+    #[test]
+    fn test_report_json() {
+        let json = serde_json::to_string(&Report::new(&story())).unwrap();
+        let report: Report = serde_json::from_str(&json).unwrap();
+        assert_eq!(report.paragraphs[1].contributor, 1);
+        assert_eq!(report.contributors[1].role, AuthorRole::Model);
+    }
+}
@@ -0,0 +1,391 @@
+use egui::{emath::TSTransform, Pos2, Rect, Vec2};
+
+/// Least zoom. At this zoom hundreds of nodes fit on screen.
+pub const MIN_ZOOM: f32 = 0.05;
+/// Greatest zoom.
+pub const MAX_ZOOM: f32 = 4.0;
+/// Below this zoom, nodes are drawn as previews rather than editors.
+pub const DETAIL_ZOOM: f32 = 0.6;
+/// Margin around content zoomed to, in points.
+const MARGIN: f32 = 32.0;
+/// Size of the minimap, in points.
+const MINIMAP_SIZE: Vec2 = Vec2::new(200.0, 150.0);
+/// Margin between the minimap and the edges of the viewport, in points.
+const MINIMAP_MARGIN: f32 = 8.0;
+
+/// An infinite surface that can be panned by dragging or scrolling and zoomed
+/// with Command/Ctrl + scroll or a pinch. Shift-dragging draws a lasso rather
+/// than panning. Content is drawn in canvas coordinates which are mapped to
+/// the viewport by a transform.
+#[derive(Clone, Debug)]
+pub struct Canvas {
+    /// Canvas to viewport transform. The viewport's top left is the origin.
+    transform: TSTransform,
+    /// Screen rectangle of the viewport, as of the last frame.
+    viewport: Rect,
+    /// Id of the node last passed to [`Canvas::follow`].
+    followed: Option<u128>,
+    /// Where the lasso being drawn started, in canvas coordinates.
+    lasso: Option<Pos2>,
+    /// Lasso finished but not yet taken by [`Canvas::take_lasso`].
+    lassoed: Option<Rect>,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self {
+            transform: TSTransform::IDENTITY,
+            viewport: Rect::NOTHING,
+            followed: None,
+            lasso: None,
+            lassoed: None,
+        }
+    }
+}
+
+static_assertions::assert_impl_all!(Canvas: Send, Sync);
+
+impl Canvas {
+    /// Current zoom. One is one point per canvas unit.
+    pub fn zoom(&self) -> f32 {
+        self.transform.scaling
+    }
+
+    /// The part of the canvas that is visible, in canvas coordinates.
+    pub fn visible_rect(&self) -> Rect {
+        self.transform.inverse()
+            * Rect::from_min_size(Pos2::ZERO, self.viewport.size())
+    }
+
+    /// Zoom and pan so that `rect`, in canvas coordinates, fills the
+    /// viewport. This never zooms in past one.
+    pub fn zoom_to(&mut self, rect: Rect) {
+        let viewport = self.viewport.shrink(MARGIN);
+        if !viewport.is_positive() || !rect.is_finite() || !rect.is_positive() {
+            return;
+        }
+        let zoom = (viewport.width() / rect.width())
+            .min(viewport.height() / rect.height())
+            .clamp(MIN_ZOOM, 1.0);
+        self.transform.scaling = zoom;
+        self.center_on(rect.center());
+    }
+
+    /// Pan, at the current zoom, so that `rect` is visible. Nothing happens if
+    /// it already is.
+    pub fn reveal(&mut self, rect: Rect) {
+        if self.viewport.is_positive()
+            && rect.is_finite()
+            && !self.visible_rect().contains_rect(rect)
+        {
+            self.center_on(rect.center());
+        }
+    }
+
+    /// [`Canvas::reveal`] a node's `rect` when the node with `id` differs from
+    /// the last one followed. Use this to keep the head in view.
+    pub fn follow(&mut self, id: u128, rect: Rect) {
+        // Until the first frame is drawn the viewport is unknown.
+        if self.followed != Some(id) && self.viewport.is_positive() {
+            self.followed = Some(id);
+            self.reveal(rect);
+        }
+    }
+
+    /// Take the rectangle, in canvas coordinates, of the lasso finished since
+    /// this was last called, if any.
+    pub fn take_lasso(&mut self) -> Option<Rect> {
+        self.lassoed.take()
+    }
+
+    /// Canvas point under the viewport point `point`.
+    fn to_canvas(&self, point: Pos2) -> Pos2 {
+        self.transform.inverse() * (point - self.viewport.min.to_vec2())
+    }
+
+    /// Pan so that the canvas point `center` is in the center of the viewport.
+    fn center_on(&mut self, center: Pos2) {
+        self.transform.translation = self.viewport.size() / 2.0
+            - center.to_vec2() * self.transform.scaling;
+    }
+
+    /// Zoom by a `factor` about a `point` in the viewport.
+    fn zoom_about(&mut self, point: Vec2, factor: f32) {
+        let zoom = (self.zoom() * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let factor = zoom / self.zoom();
+        self.transform = TSTransform::from_translation(point)
+            * TSTransform::from_scaling(factor)
+            * TSTransform::from_translation(-point)
+            * self.transform;
+    }
+
+    /// Fill the available space with the canvas and draw `add_contents` on
+    /// it. The [`egui::Ui`] passed to `add_contents` is in canvas
+    /// coordinates and its clip rectangle is the visible part of the canvas.
+    pub fn show<R>(
+        &mut self,
+        ui: &mut egui::Ui,
+        add_contents: impl FnOnce(&mut egui::Ui) -> R,
+    ) -> R {
+        let (viewport, response) = ui.allocate_exact_size(
+            ui.available_size(),
+            egui::Sense::click_and_drag(),
+        );
+        self.viewport = viewport;
+
+        // Shift-dragging the background draws a lasso, otherwise dragging
+        // pans.
+        if response.drag_started() && ui.input(|input| input.modifiers.shift) {
+            self.lasso =
+                response.interact_pointer_pos().map(|p| self.to_canvas(p));
+        }
+        if let Some(start) = self.lasso {
+            let pointer = ui.input(|input| input.pointer.interact_pos());
+            let end = pointer.map_or(start, |p| self.to_canvas(p));
+            let rect = Rect::from_two_pos(start, end);
+            if response.drag_stopped() || !response.dragged() {
+                self.lasso = None;
+                self.lassoed = Some(rect);
+            } else {
+                let screen =
+                    TSTransform::from_translation(viewport.min.to_vec2())
+                        * self.transform
+                        * rect;
+                let selection = ui.visuals().selection;
+                ui.ctx()
+                    .layer_painter(egui::LayerId::new(
+                        egui::Order::Foreground,
+                        ui.id().with("lasso"),
+                    ))
+                    .with_clip_rect(viewport)
+                    .rect(
+                        screen,
+                        0.0,
+                        selection.bg_fill.gamma_multiply(0.25),
+                        selection.stroke,
+                    );
+            }
+        } else if response.dragged() {
+            self.transform.translation += response.drag_delta();
+        }
+        if ui.rect_contains_pointer(viewport) {
+            let (pointer, zoom, scroll) = ui.input(|input| {
+                (
+                    input.pointer.hover_pos(),
+                    input.zoom_delta(),
+                    input.smooth_scroll_delta,
+                )
+            });
+            if let Some(pointer) = pointer {
+                if zoom != 1.0 {
+                    self.zoom_about(pointer - viewport.min, zoom);
+                }
+            }
+            self.transform.translation += scroll;
+        }
+
+        let transform = TSTransform::from_translation(viewport.min.to_vec2())
+            * self.transform;
+        let area = egui::Area::new(ui.id().with("canvas"))
+            .fixed_pos(Pos2::ZERO)
+            .constrain(false)
+            // Clicks on the background go through to the canvas.
+            .interactable(false)
+            .show(ui.ctx(), |ui| {
+                ui.set_clip_rect(transform.inverse() * viewport);
+                add_contents(ui)
+            });
+        ui.ctx()
+            .set_transform_layer(area.response.layer_id, transform);
+
+        area.inner
+    }
+
+    /// Draw a minimap of `rects`, in canvas coordinates, in the bottom right
+    /// corner of the viewport. Each rect is paired with whether it should be
+    /// highlighted. The visible part of the canvas is outlined. Clicking or
+    /// dragging on the minimap pans there. Call this after [`Canvas::show`].
+    pub fn minimap(
+        &mut self,
+        ui: &egui::Ui,
+        rects: impl IntoIterator<Item = (Rect, bool)>,
+    ) {
+        if !self.viewport.is_positive() {
+            return;
+        }
+        let visible = self.visible_rect();
+        let (highlighted, rects): (Vec<_>, Vec<_>) =
+            rects.into_iter().partition(|&(_, highlighted)| highlighted);
+        let bounds = rects
+            .iter()
+            .chain(&highlighted)
+            .fold(visible, |bounds, &(rect, _)| bounds.union(rect));
+
+        let pos =
+            self.viewport.max - MINIMAP_SIZE - Vec2::splat(MINIMAP_MARGIN);
+        egui::Area::new(ui.id().with("minimap"))
+            .order(egui::Order::Foreground)
+            .fixed_pos(pos)
+            .show(ui.ctx(), |ui| {
+                let (rect, response) = ui.allocate_exact_size(
+                    MINIMAP_SIZE,
+                    egui::Sense::click_and_drag(),
+                );
+                let to_map = fit(bounds, rect.shrink(2.0));
+                if response.is_pointer_button_down_on() {
+                    if let Some(pointer) = response.interact_pointer_pos() {
+                        self.center_on(to_map.inverse() * pointer);
+                    }
+                }
+
+                let visuals = ui.visuals();
+                let painter = ui.painter();
+                painter.rect(
+                    rect,
+                    visuals.window_rounding,
+                    visuals.extreme_bg_color.gamma_multiply(0.8),
+                    visuals.window_stroke,
+                );
+                // Nodes are at least a point so they don't vanish.
+                let at_least_a_point = |rect: Rect| {
+                    Rect::from_min_size(
+                        rect.min,
+                        rect.size().max(Vec2::splat(1.0)),
+                    )
+                };
+                for (node, _) in rects {
+                    painter.rect_filled(
+                        at_least_a_point(to_map * node),
+                        0.0,
+                        visuals.widgets.inactive.bg_fill,
+                    );
+                }
+                for (node, _) in highlighted {
+                    painter.rect_filled(
+                        at_least_a_point(to_map * node),
+                        0.0,
+                        visuals.selection.bg_fill,
+                    );
+                }
+                painter.rect_stroke(
+                    to_map * visible,
+                    0.0,
+                    egui::Stroke::new(1.0, visuals.strong_text_color()),
+                );
+            });
+    }
+}
+
+/// Transform that fits `bounds` into `rect`, centered, keeping the aspect
+/// ratio.
+fn fit(bounds: Rect, rect: Rect) -> TSTransform {
+    let scaling =
+        (rect.width() / bounds.width()).min(rect.height() / bounds.height());
+    TSTransform::new(
+        rect.center().to_vec2() - bounds.center().to_vec2() * scaling,
+        scaling,
+    )
+}
+
+/// Zoom of the canvas that `ui` is drawn on, or one if it isn't on one.
+pub fn zoom(ui: &egui::Ui) -> f32 {
+    ui.ctx().memory(|memory| {
+        memory
+            .layer_transforms
+            .get(&ui.layer_id())
+            .map_or(1.0, |transform| transform.scaling)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canvas() -> Canvas {
+        Canvas {
+            viewport: Rect::from_min_size(
+                Pos2::new(100.0, 50.0),
+                Vec2::new(800.0, 600.0),
+            ),
+            ..Canvas::default()
+        }
+    }
+
+    #[test]
+    fn test_zoom_to() {
+        let mut canvas = canvas();
+        let rect =
+            Rect::from_min_size(Pos2::new(-500.0, 0.0), Vec2::splat(1000.0));
+        canvas.zoom_to(rect);
+        assert!(canvas.zoom() < 1.0);
+        let visible = canvas.visible_rect();
+        assert!(visible.contains_rect(rect));
+        assert!((visible.center() - rect.center()).length() < 0.01);
+
+        // Small things aren't magnified.
+        canvas.zoom_to(Rect::from_min_size(Pos2::ZERO, Vec2::splat(10.0)));
+        assert_eq!(canvas.zoom(), 1.0);
+    }
+
+    #[test]
+    fn test_reveal_and_follow() {
+        let mut canvas = canvas();
+        let visible = canvas.visible_rect();
+        let inside =
+            Rect::from_min_size(Pos2::new(10.0, 10.0), Vec2::splat(50.0));
+        canvas.reveal(inside);
+        assert_eq!(canvas.visible_rect(), visible);
+
+        let outside = inside.translate(Vec2::new(5000.0, 0.0));
+        canvas.follow(1, outside);
+        assert!(canvas.visible_rect().contains_rect(outside));
+
+        // Following the same node again doesn't pan.
+        canvas.transform.translation = Vec2::ZERO;
+        canvas.follow(1, outside);
+        assert_eq!(canvas.visible_rect(), visible);
+    }
+
+    #[test]
+    fn test_to_canvas() {
+        let mut canvas = canvas();
+        canvas.transform = TSTransform::new(Vec2::new(10.0, 20.0), 2.0);
+        let point = canvas.to_canvas(Pos2::new(130.0, 90.0));
+        assert_eq!(point, Pos2::new(10.0, 10.0));
+        assert!(canvas.take_lasso().is_none());
+    }
+
+    #[test]
+    fn test_fit() {
+        let bounds = Rect::from_min_size(
+            Pos2::new(-500.0, 0.0),
+            Vec2::new(1000.0, 500.0),
+        );
+        let rect =
+            Rect::from_min_size(Pos2::new(10.0, 10.0), Vec2::new(200.0, 200.0));
+        let transform = fit(bounds, rect);
+        let fitted = transform * bounds;
+        assert!(rect.expand(0.01).contains_rect(fitted));
+        assert_eq!(fitted.width(), rect.width());
+        assert!((fitted.center() - rect.center()).length() < 0.01);
+        // The minimap pans back through the inverse.
+        let point = Pos2::new(100.0, 100.0);
+        let back = transform * (transform.inverse() * point);
+        assert!((back - point).length() < 0.01);
+    }
+
+    #[test]
+    fn test_zoom_about() {
+        let mut canvas = canvas();
+        let point = Vec2::new(200.0, 100.0);
+        let before = canvas.transform.inverse() * point.to_pos2();
+        canvas.zoom_about(point, 2.0);
+        assert_eq!(canvas.zoom(), 2.0);
+        // The point under the pointer stays put.
+        let after = canvas.transform.inverse() * point.to_pos2();
+        assert!((after - before).length() < 0.01);
+
+        canvas.zoom_about(point, 1000.0);
+        assert_eq!(canvas.zoom(), MAX_ZOOM);
+    }
+}
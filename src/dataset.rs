@@ -0,0 +1,338 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    node::{Meta, Node},
+    story::{AuthorRole, Story},
+};
+
+/// Fine-tuning dataset format. Each is written as JSON Lines.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum Format {
+    /// Supervised fine-tuning: `{"prompt", "completion"}` for each node.
+    #[default]
+    Sft,
+    /// Direct preference optimization: `{"prompt", "chosen", "rejected"}` for
+    /// each pair of siblings where one is preferred to the other.
+    Dpo,
+    /// Kahneman-Tversky optimization: `{"prompt", "completion", "label"}`
+    /// for each node that is either desirable or undesirable.
+    Kto,
+}
+
+impl Format {
+    pub const ALL: [Self; 3] = [Self::Sft, Self::Dpo, Self::Kto];
+
+    pub const fn as_str(&self) -> &str {
+        match self {
+            Self::Sft => "SFT",
+            Self::Dpo => "DPO",
+            Self::Kto => "KTO",
+        }
+    }
+}
+
+/// Dataset export options.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    pub format: Format,
+    /// Comma separated names of people whose nodes are included. If empty,
+    /// all people are included.
+    pub authors: String,
+    /// Comma separated names of models whose nodes are included. If empty,
+    /// all models are included.
+    pub models: String,
+    /// Nodes rated below this are excluded from SFT, are never chosen for
+    /// DPO, and are undesirable for KTO. Zero means no minimum.
+    pub min_rating: u8,
+    /// Whether prompts include the story's authors.
+    pub include_authors: bool,
+    /// Whether prompts include the story's title.
+    pub include_title: bool,
+}
+
+static_assertions::assert_impl_all!(Options: Send, Sync);
+
+#[derive(Serialize)]
+struct Sft<'a> {
+    prompt: &'a str,
+    completion: &'a str,
+}
+
+#[derive(Serialize)]
+struct Dpo<'a> {
+    prompt: &'a str,
+    chosen: &'a str,
+    rejected: &'a str,
+}
+
+#[derive(Serialize)]
+struct Kto<'a> {
+    prompt: &'a str,
+    completion: &'a str,
+    label: bool,
+}
+
+impl Options {
+    /// Export a `story` as JSON Lines. Returns the dataset and the number of
+    /// records in it.
+    pub fn export(
+        &self,
+        story: &Story,
+    ) -> Result<(String, usize), serde_json::Error> {
+        let mut out = String::new();
+        let mut records = 0;
+        let mut push = |record: Result<String, serde_json::Error>| {
+            out.push_str(&record?);
+            out.push('\n');
+            records += 1;
+            Ok::<_, serde_json::Error>(())
+        };
+
+        for (path, parent) in story.root().iter_depth_first_paths() {
+            let children: Vec<(usize, &Node<Meta>)> = parent
+                .children
+                .iter()
+                .enumerate()
+                .filter(|(_, child)| self.includes_author(story, child))
+                .collect();
+            let Some(&(first, _)) = children.first() else {
+                continue;
+            };
+            // Siblings share a prompt.
+            let prompt =
+                self.prompt(story, &[path.as_slice(), &[first]].concat());
+
+            match self.format {
+                Format::Sft => {
+                    for (_, child) in children {
+                        if self.is_desirable(child) == Some(true)
+                            || (self.min_rating == 0 && !child.flags.rejected)
+                        {
+                            push(serde_json::to_string(&Sft {
+                                prompt: &prompt,
+                                completion: &child.text,
+                            }))?;
+                        }
+                    }
+                }
+                Format::Dpo => {
+                    // Only nodes that have been rated or flagged are compared.
+                    let children: Vec<_> = children
+                        .into_iter()
+                        .map(|(_, child)| child)
+                        .filter(|child| {
+                            child.rating.is_some()
+                                || child.flags.keep
+                                || child.flags.rejected
+                        })
+                        .collect();
+                    for &chosen in children.iter() {
+                        if self.min_rating > 0
+                            && self.is_desirable(chosen) != Some(true)
+                        {
+                            continue;
+                        }
+                        for &rejected in children.iter() {
+                            if preference(chosen) > preference(rejected) {
+                                push(serde_json::to_string(&Dpo {
+                                    prompt: &prompt,
+                                    chosen: &chosen.text,
+                                    rejected: &rejected.text,
+                                }))?;
+                            }
+                        }
+                    }
+                }
+                Format::Kto => {
+                    for (_, child) in children {
+                        if let Some(label) = self.is_desirable(child) {
+                            push(serde_json::to_string(&Kto {
+                                prompt: &prompt,
+                                completion: &child.text,
+                                label,
+                            }))?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok((out, records))
+    }
+
+    /// Draw the options editor. Prompt options are left to the caller.
+    #[cfg(feature = "gui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for format in Format::ALL {
+                ui.selectable_value(&mut self.format, format, format.as_str());
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("People");
+            ui.text_edit_singleline(&mut self.authors)
+                .on_hover_text_at_pointer(
+                    "Comma separated names. Leave empty to include everyone.",
+                );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Models");
+            ui.text_edit_singleline(&mut self.models)
+                .on_hover_text_at_pointer(
+                    "Comma separated names. Leave empty to include all models.",
+                );
+        });
+        ui.add(
+            egui::Slider::new(&mut self.min_rating, 0..=crate::node::MAX_RATING)
+                .text("Minimum rating"),
+        )
+        .on_hover_text_at_pointer(
+            "Nodes rated below this are excluded (SFT), never chosen (DPO) or undesirable (KTO). Zero means no minimum.",
+        );
+    }
+
+    /// The prompt for the node at `path`.
+    fn prompt(&self, story: &Story, path: &[usize]) -> String {
+        let mut prompt = String::new();
+        // Writing to a String can't fail.
+        story
+            .format_prompt(
+                &mut prompt,
+                path,
+                self.include_authors,
+                self.include_title,
+            )
+            .unwrap();
+        prompt
+    }
+
+    /// Whether a `node` is not empty and was written by an included author.
+    fn includes_author(&self, story: &Story, node: &Node<Meta>) -> bool {
+        if node.text.trim().is_empty() {
+            return false;
+        }
+        let name = story.author_name(node.author_id).unwrap_or_default();
+        let names = match story.author_profile(node.author_id).role {
+            AuthorRole::Human => &self.authors,
+            AuthorRole::Model => &self.models,
+        };
+        names.trim().is_empty()
+            || names.split(',').any(|n| n.trim() == name.trim())
+    }
+
+    /// Whether a `node` is desirable. Nodes flagged as rejected or rated below
+    /// the minimum are not. Nodes flagged as kept or rated at or above the
+    /// minimum are. Otherwise, this is unknown.
+    fn is_desirable(&self, node: &Node<Meta>) -> Option<bool> {
+        if node.flags.rejected {
+            Some(false)
+        } else if node.flags.keep {
+            Some(true)
+        } else {
+            node.rating.map(|r| r >= self.min_rating)
+        }
+    }
+}
+
+/// Order siblings by preference, as [`Node::best_child`] does. Siblings that
+/// compare equal don't form preference pairs.
+fn preference(node: &Node<Meta>) -> (bool, Option<u8>, bool) {
+    (!node.flags.rejected, node.rating, node.flags.keep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A story where a person writes the start and a model writes three
+    /// alternatives for what comes next.
+    fn story() -> Story {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["Once upon a time."]);
+        let model = story.add_model_author("gpt").unwrap();
+        for (text, rating) in
+            [("Good.", Some(5)), ("Bad.", Some(1)), ("Meh.", None)]
+        {
+            story.set_active_path(vec![0]);
+            story.add_paragraph(model, [text]);
+            story.head_mut().rating = rating;
+        }
+        story
+    }
+
+    fn lines(options: &Options, story: &Story) -> Vec<serde_json::Value> {
+        let (jsonl, count) = options.export(story).unwrap();
+        let lines: Vec<serde_json::Value> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), count);
+        lines
+    }
+
+    #[test]
+    fn test_sft() {
+        let story = story();
+        let mut options = Options::default();
+        let records = lines(&options, &story);
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0]["prompt"], "\n");
+        assert_eq!(records[0]["completion"], "Once upon a time.");
+        // The root is empty, but still separated from its children.
+        assert_eq!(records[1]["prompt"], "\nOnce upon a time.\n");
+
+        options.min_rating = 3;
+        let records = lines(&options, &story);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["completion"], "Good.");
+
+        options.min_rating = 0;
+        options.models = "llama".to_string();
+        assert_eq!(lines(&options, &story).len(), 1);
+        options.authors = "Bob".to_string();
+        assert!(lines(&options, &story).is_empty());
+    }
+
+    #[test]
+    fn test_dpo() {
+        let mut story = story();
+        let options = Options {
+            format: Format::Dpo,
+            include_title: true,
+            ..Options::default()
+        };
+        let records = lines(&options, &story);
+        // Unrated nodes aren't compared.
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["prompt"], "# Test\n\n\nOnce upon a time.\n");
+        assert_eq!(records[0]["chosen"], "Good.");
+        assert_eq!(records[0]["rejected"], "Bad.");
+
+        story.node_mut(&[0, 1]).unwrap().flags.rejected = true;
+        story.node_mut(&[0, 2]).unwrap().flags.keep = true;
+        let records = lines(&options, &story);
+        // Good > Bad, Good > Meh, Meh > Bad
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1]["rejected"], "Meh.");
+        assert_eq!(records[2]["chosen"], "Meh.");
+        assert_eq!(records[2]["rejected"], "Bad.");
+    }
+
+    #[test]
+    fn test_kto() {
+        let story = story();
+        let options = Options {
+            format: Format::Kto,
+            min_rating: 3,
+            ..Options::default()
+        };
+        let records = lines(&options, &story);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["label"], true);
+        assert_eq!(records[1]["completion"], "Bad.");
+        assert_eq!(records[1]["label"], false);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::{
+    node::{Meta, Node},
+    story::Story,
+};
+
+/// Whether a [`Span`] of a branch's text is shared with the baseline branch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tag {
+    /// Text in both the branch and the baseline.
+    Same,
+    /// Text only in the baseline.
+    Removed,
+    /// Text only in the branch.
+    Added,
+}
+
+/// A run of text with the same [`Tag`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub tag: Tag,
+    pub text: String,
+}
+
+/// Word-level diff of `old` and `new`. Returns the spans of `old` (tagged
+/// [`Tag::Same`] or [`Tag::Removed`]) and those of `new` ([`Tag::Same`] or
+/// [`Tag::Added`]). Adjacent spans with the same tag are merged.
+pub fn diff_words(old: &str, new: &str) -> (Vec<Span>, Vec<Span>) {
+    let diff = similar::TextDiff::from_words(old, new);
+    let mut old_spans = Vec::new();
+    let mut new_spans = Vec::new();
+    for change in diff.iter_all_changes() {
+        let (spans, tag) = match change.tag() {
+            similar::ChangeTag::Equal => {
+                push(&mut old_spans, Tag::Same, change.value());
+                (&mut new_spans, Tag::Same)
+            }
+            similar::ChangeTag::Delete => (&mut old_spans, Tag::Removed),
+            similar::ChangeTag::Insert => (&mut new_spans, Tag::Added),
+        };
+        push(spans, tag, change.value());
+    }
+    (old_spans, new_spans)
+}
+
+/// Push `text` onto `spans`, merging with the last span if the tags match.
+fn push(spans: &mut Vec<Span>, tag: Tag, text: &str) {
+    match spans.last_mut() {
+        Some(last) if last.tag == tag => last.text.push_str(text),
+        _ => spans.push(Span {
+            tag,
+            text: text.to_string(),
+        }),
+    }
+}
+
+/// Statistics for a [`Branch`] relative to the baseline.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    /// Length of the branch text in characters.
+    pub chars: usize,
+    /// Number of words in the branch text.
+    pub words: usize,
+    /// Length of the text shared with the start of the baseline, in
+    /// characters. For the baseline, this is relative to the second branch.
+    pub shared_prefix: usize,
+    /// Fraction of the text, by characters, that is the same as the baseline.
+    pub similarity: f32,
+}
+
+impl Stats {
+    /// Stats for a branch's `text` diffed into `spans`.
+    fn new(text: &str, spans: &[Span], shared_prefix: usize) -> Self {
+        let chars = text.chars().count();
+        let same: usize = spans
+            .iter()
+            .filter(|span| span.tag == Tag::Same)
+            .map(|span| span.text.chars().count())
+            .sum();
+        Self {
+            chars,
+            words: text.split_whitespace().count(),
+            shared_prefix,
+            similarity: if chars == 0 {
+                1.0
+            } else {
+                same as f32 / chars as f32
+            },
+        }
+    }
+}
+
+/// One side of a [`Comparison`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Branch {
+    /// Path from the root to the last node of the branch.
+    pub path: Vec<usize>,
+    /// Text of the branch after the shared path, one node per line.
+    pub text: String,
+    /// `text` diffed against the baseline. The baseline itself is diffed
+    /// against the second branch.
+    pub spans: Vec<Span>,
+    pub stats: Stats,
+}
+
+/// Branches of a [`Story`], side by side. The first branch is the baseline
+/// that the others are diffed against.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Comparison {
+    /// Path from the root to the last node the branches have in common.
+    pub shared_path: Vec<usize>,
+    pub branches: Vec<Branch>,
+}
+
+impl Comparison {
+    /// Compare the children of the node at `path`. There are no branches if
+    /// the path is invalid.
+    pub fn children(story: &Story, path: &[usize]) -> Self {
+        Self::new(story, Self::child_paths(story, path))
+    }
+
+    /// Paths to the children of the node at `path`, or none if the path is
+    /// invalid.
+    pub fn child_paths(story: &Story, path: &[usize]) -> Vec<Vec<usize>> {
+        let Some(node) = story.root().iter_path_nodes(path).nth(path.len())
+        else {
+            return Vec::new();
+        };
+        (0..node.children.len())
+            .map(|i| [path, &[i]].concat())
+            .collect()
+    }
+
+    /// Compare any `paths` through a `story`. Invalid paths are skipped.
+    pub fn new(story: &Story, mut paths: Vec<Vec<usize>>) -> Self {
+        let root = story.root();
+        paths.retain(|path| root.is_valid_path(path));
+
+        let shared = paths.first().map_or(0, |first| {
+            paths.iter().skip(1).fold(first.len(), |len, path| {
+                first
+                    .iter()
+                    .zip(path)
+                    .take_while(|(a, b)| a == b)
+                    .count()
+                    .min(len)
+            })
+        });
+        let shared_path = paths
+            .first()
+            .map_or(Vec::new(), |path| path[..shared].to_vec());
+
+        let texts: Vec<String> = paths
+            .iter()
+            .map(|path| branch_text(root, path, shared))
+            .collect();
+
+        let mut branches: Vec<Branch> = paths
+            .into_iter()
+            .zip(texts.iter())
+            .map(|(path, text)| {
+                let spans = vec![Span {
+                    tag: Tag::Same,
+                    text: text.clone(),
+                }];
+                Branch {
+                    path,
+                    text: text.clone(),
+                    stats: Stats::new(text, &spans, text.chars().count()),
+                    spans,
+                }
+            })
+            .collect();
+
+        for i in 1..branches.len() {
+            let (base, spans) = diff_words(&texts[0], &texts[i]);
+            let shared_prefix = texts[0]
+                .chars()
+                .zip(texts[i].chars())
+                .take_while(|(a, b)| a == b)
+                .count();
+            if i == 1 {
+                branches[0].stats = Stats::new(&texts[0], &base, shared_prefix);
+                branches[0].spans = base;
+            }
+            branches[i].stats = Stats::new(&texts[i], &spans, shared_prefix);
+            branches[i].spans = spans;
+        }
+
+        Self {
+            shared_path,
+            branches,
+        }
+    }
+
+    /// Returns true if comparing `paths` through a `story` would give this
+    /// comparison, so it needn't be diffed again. This only joins the text of
+    /// each branch.
+    pub fn is_current(&self, story: &Story, paths: &[Vec<usize>]) -> bool {
+        let root = story.root();
+        let mut paths = paths.iter().filter(|path| root.is_valid_path(path));
+        let skip = self.shared_path.len();
+        self.branches.iter().all(|branch| {
+            paths.next().is_some_and(|path| {
+                branch.path == *path
+                    && branch.text == branch_text(root, path, skip)
+            })
+        }) && paths.next().is_none()
+    }
+}
+
+/// Text of the nodes along `path` after the first `skip` steps.
+fn branch_text(root: &Node<Meta>, path: &[usize], skip: usize) -> String {
+    root.iter_path_nodes(path)
+        .skip(skip + 1)
+        .map(|node| node.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_words() {
+        let (old, new) = diff_words("The cat sat down.", "The dog sat down.");
+        assert_eq!(
+            old,
+            vec![
+                Span {
+                    tag: Tag::Same,
+                    text: "The ".to_string()
+                },
+                Span {
+                    tag: Tag::Removed,
+                    text: "cat".to_string()
+                },
+                Span {
+                    tag: Tag::Same,
+                    text: " sat down.".to_string()
+                },
+            ]
+        );
+        assert_eq!(new[1].tag, Tag::Added);
+        assert_eq!(new[1].text, "dog");
+        assert_eq!(new.len(), 3);
+    }
+
+    #[test]
+    fn test_comparison() {
+        let mut story = Story::new("A".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["Once upon a time."]);
+        story.add_paragraph("Alice", ["The cat sat."]);
+        story.add_paragraph("Alice", ["It slept."]);
+        story.set_active_path(vec![0]);
+        story.add_paragraph("Alice", ["The cat ran away."]);
+
+        let comparison = Comparison::children(&story, &[0]);
+        assert_eq!(comparison.shared_path, vec![0]);
+        assert_eq!(comparison.branches.len(), 2);
+        let [a, b] = &comparison.branches[..] else {
+            unreachable!()
+        };
+        assert_eq!(a.text, "The cat sat.");
+        assert_eq!(a.stats.chars, 12);
+        assert_eq!(b.stats.words, 4);
+        assert_eq!(b.stats.shared_prefix, 8);
+        assert!(b.stats.similarity > 0.0 && b.stats.similarity < 1.0);
+        assert!(a.spans.iter().any(|s| s.tag == Tag::Removed));
+        assert!(b.spans.iter().any(|s| s.tag == Tag::Added));
+
+        // Paths of different lengths share only their common prefix.
+        let comparison =
+            Comparison::new(&story, vec![vec![0, 0, 0], vec![0, 1], vec![9]]);
+        assert_eq!(comparison.shared_path, vec![0]);
+        assert_eq!(comparison.branches.len(), 2);
+        assert_eq!(comparison.branches[0].text, "The cat sat.\nIt slept.");
+
+        assert!(Comparison::children(&story, &[0, 1]).branches.is_empty());
+
+        // Only a change to the compared paths or their text needs a new diff.
+        let paths = Comparison::child_paths(&story, &[0]);
+        let comparison = Comparison::new(&story, paths.clone());
+        assert!(comparison.is_current(&story, &paths));
+        assert!(!comparison.is_current(&story, &paths[..1]));
+        story.node_mut(&[0, 1]).unwrap().text = "The dog ran away.".into();
+        assert!(!comparison.is_current(&story, &paths));
+    }
+}
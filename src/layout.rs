@@ -0,0 +1,660 @@
+use egui::{Pos2, Rect, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::node::{Meta, Node};
+
+/// Fraction of the remaining distance to its target a node moves per second.
+const ANIMATION_SPEED: f32 = 8.0;
+/// Nodes closer than this to their targets snap to them.
+const SNAP: f32 = 0.5;
+/// Barnes–Hut opening angle. A cell whose width is less than this fraction of
+/// its distance is treated as a single mass. Zero is exact.
+pub const THETA: f32 = 0.7;
+/// Cells are not split past this depth. Bodies that fall in the same cell at
+/// this depth are merged, which also handles bodies at the same position.
+const MAX_DEPTH: usize = 24;
+/// Distance added in quadrature to avoid infinite forces between nodes that
+/// are very close together.
+const SOFTENING: f32 = 32.0;
+/// Nodes are offset by up to this distance, for the purpose of computing
+/// forces, so that nodes on top of each other are pushed apart.
+const JITTER: f32 = 0.1;
+/// Fraction of velocity lost each step of the simulation.
+const DAMPING: f32 = 0.1;
+/// Greatest distance a node moves in one step of the simulation.
+const MAX_SPEED: f32 = 32.0;
+/// Below this speed a node comes to rest.
+const REST_SPEED: f32 = 0.1;
+/// Edges pull like springs, with force proportional to length over this.
+const EDGE_SCALE: f32 = 50_000.0;
+/// Least mass of a node, so that nodes not yet drawn still take part.
+const MIN_MASS: f32 = 1.0;
+
+/// Direction a [`tidy`] tree grows in.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum Direction {
+    #[default]
+    TopDown,
+    LeftRight,
+}
+
+impl Direction {
+    pub const ALL: [Self; 2] = [Self::TopDown, Self::LeftRight];
+
+    pub const fn as_str(&self) -> &str {
+        match self {
+            Self::TopDown => "Top down",
+            Self::LeftRight => "Left to right",
+        }
+    }
+
+    /// Size of a node across the tree (between siblings).
+    fn breadth(self, size: Vec2) -> f32 {
+        match self {
+            Self::TopDown => size.x,
+            Self::LeftRight => size.y,
+        }
+    }
+
+    /// Size of a node along the tree (from parent to child).
+    fn depth(self, size: Vec2) -> f32 {
+        match self {
+            Self::TopDown => size.y,
+            Self::LeftRight => size.x,
+        }
+    }
+}
+
+/// Nodes in depth-first preorder, the order positions are returned in.
+fn preorder(root: &Node<Meta>) -> Vec<(usize, &Node<Meta>)> {
+    let mut nodes = Vec::new();
+    let mut stack = vec![(0, root)];
+    while let Some((depth, node)) = stack.pop() {
+        nodes.push((depth, node));
+        stack.extend(node.children.iter().rev().map(|c| (depth + 1, c)));
+    }
+    nodes
+}
+
+/// Metadata of nodes, and the index of their parents, in depth-first
+/// preorder.
+fn preorder_mut(root: &mut Node<Meta>) -> Vec<(Option<usize>, &mut Meta)> {
+    let mut metas = Vec::new();
+    let mut stack = vec![(None, root)];
+    while let Some((parent, Node { meta, children, .. })) = stack.pop() {
+        let index = Some(metas.len());
+        metas.push((parent, meta));
+        stack.extend(children.iter_mut().rev().map(|c| (index, c)));
+    }
+    metas
+}
+
+/// A laid out subtree, relative to the center of its root.
+struct Subtree {
+    /// Offset across the tree of each node, in preorder.
+    offsets: Vec<f32>,
+    /// Least and greatest extent across the tree at each depth.
+    contour: Vec<(f32, f32)>,
+}
+
+/// Lay out a subtree so that siblings, and their subtrees, are `gap` apart
+/// and each parent is centered over its children.
+fn subtree(node: &Node<Meta>, direction: Direction, gap: f32) -> Subtree {
+    let half = direction.breadth(node.meta.size) / 2.0;
+    let mut children = node
+        .children
+        .iter()
+        .map(|child| subtree(child, direction, gap));
+    let Some(first) = children.next() else {
+        return Subtree {
+            offsets: vec![0.0],
+            contour: vec![(-half, half)],
+        };
+    };
+
+    // Each subtree is placed as close to those left of it as their contours
+    // allow.
+    let mut contour = first.contour.clone();
+    let mut placed = vec![(0.0, first)];
+    for child in children {
+        let shift = contour
+            .iter()
+            .zip(child.contour.iter())
+            .map(|(&(_, right), &(left, _))| right + gap - left)
+            .fold(f32::MIN, f32::max);
+        for (depth, &(left, right)) in child.contour.iter().enumerate() {
+            let (left, right) = (left + shift, right + shift);
+            match contour.get_mut(depth) {
+                Some(extent) => {
+                    extent.0 = extent.0.min(left);
+                    extent.1 = extent.1.max(right);
+                }
+                None => contour.push((left, right)),
+            }
+        }
+        placed.push((shift, child));
+    }
+
+    // Center the parent over its first and last children.
+    let center = (placed[0].0 + placed[placed.len() - 1].0) / 2.0;
+    let mut offsets = vec![0.0];
+    for (shift, child) in placed {
+        offsets.extend(child.offsets.iter().map(|o| o + shift - center));
+    }
+    let contour = std::iter::once((-half, half))
+        .chain(contour.into_iter().map(|(l, r)| (l - center, r - center)))
+        .collect();
+
+    Subtree { offsets, contour }
+}
+
+/// Tidy, layered tree layout in the manner of Reingold and Tilford. Siblings
+/// are `sibling_gap` apart and layers `layer_gap` apart. Returns the position
+/// of each node in depth-first preorder, with the root where it is now.
+pub fn tidy(
+    root: &Node<Meta>,
+    direction: Direction,
+    sibling_gap: f32,
+    layer_gap: f32,
+) -> Vec<Pos2> {
+    let nodes = preorder(root);
+    let offsets = subtree(root, direction, sibling_gap).offsets;
+
+    // Layers are as deep as their deepest node.
+    let mut layers: Vec<f32> = Vec::new();
+    for &(depth, node) in nodes.iter() {
+        let size = direction.depth(node.meta.size);
+        match layers.get_mut(depth) {
+            Some(layer) => *layer = layer.max(size),
+            None => layers.push(size),
+        }
+    }
+    let starts: Vec<f32> = layers
+        .iter()
+        .scan(0.0, |start, layer| {
+            let this = *start;
+            *start += layer + layer_gap;
+            Some(this)
+        })
+        .collect();
+
+    let positions: Vec<Pos2> = nodes
+        .iter()
+        .zip(offsets)
+        .map(|(&(depth, node), offset)| {
+            let across = offset - direction.breadth(node.meta.size) / 2.0;
+            match direction {
+                Direction::TopDown => Pos2::new(across, starts[depth]),
+                Direction::LeftRight => Pos2::new(starts[depth], across),
+            }
+        })
+        .collect();
+    anchor(root, positions)
+}
+
+/// Radial layout. The root is in the center and each generation is on a ring
+/// around it, at least `ring_gap` from the last. Each subtree gets a wedge in
+/// proportion to its leaves. Returns the position of each node in
+/// depth-first preorder, with the root where it is now.
+pub fn radial(root: &Node<Meta>, ring_gap: f32) -> Vec<Pos2> {
+    let nodes = preorder(root);
+
+    // The radius of each ring makes room for the largest nodes on it, and for
+    // all the nodes on it side by side.
+    let mut largest: Vec<f32> = Vec::new();
+    let mut total: Vec<f32> = Vec::new();
+    for &(depth, node) in nodes.iter() {
+        let size = node.meta.size.length();
+        if depth == largest.len() {
+            largest.push(0.0);
+            total.push(0.0);
+        }
+        largest[depth] = largest[depth].max(size);
+        total[depth] += size;
+    }
+    let mut radii = vec![0.0];
+    for depth in 1..largest.len() {
+        let radius = radii[depth - 1]
+            + ring_gap
+            + (largest[depth - 1] + largest[depth]) / 2.0;
+        radii.push(radius.max(total[depth] / std::f32::consts::TAU));
+    }
+
+    let mut positions = Vec::with_capacity(nodes.len());
+    // Each node's wedge, start and end angles, in preorder.
+    let mut stack = vec![(0, root, 0.0, std::f32::consts::TAU)];
+    while let Some((depth, node, start, end)) = stack.pop() {
+        let angle = (start + end) / 2.0;
+        let center = if depth == 0 {
+            Pos2::ZERO
+        } else {
+            Pos2::new(angle.cos(), angle.sin()) * radii[depth]
+        };
+        positions.push(center - node.meta.size / 2.0);
+
+        let leaves: Vec<usize> = node.children.iter().map(leaf_count).collect();
+        let per_leaf =
+            (end - start) / leaves.iter().sum::<usize>().max(1) as f32;
+        let mut wedges = Vec::with_capacity(leaves.len());
+        let mut from = start;
+        for (child, leaves) in node.children.iter().zip(leaves) {
+            let to = from + per_leaf * leaves as f32;
+            wedges.push((depth + 1, child, from, to));
+            from = to;
+        }
+        stack.extend(wedges.into_iter().rev());
+    }
+    anchor(root, positions)
+}
+
+/// Number of leaves in a subtree. A leaf counts itself.
+fn leaf_count(node: &Node<Meta>) -> usize {
+    if node.children.is_empty() {
+        1
+    } else {
+        node.children.iter().map(leaf_count).sum()
+    }
+}
+
+/// Translate `positions` so the root stays where it is.
+fn anchor(root: &Node<Meta>, mut positions: Vec<Pos2>) -> Vec<Pos2> {
+    let offset = root.meta.pos - positions[0];
+    for pos in positions.iter_mut() {
+        *pos += offset;
+    }
+    positions
+}
+
+/// Move each node, in depth-first preorder, toward its `target`. Pinned nodes
+/// stay put. Returns true if any node moved, in which case a redraw is needed.
+pub fn animate(
+    root: &mut Node<Meta>,
+    targets: &[Pos2],
+    time_step: f32,
+) -> bool {
+    let step = (time_step * ANIMATION_SPEED).min(1.0);
+    let mut moved = false;
+    for ((_, meta), &target) in preorder_mut(root).into_iter().zip(targets) {
+        if meta.pinned {
+            continue;
+        }
+        let delta = target - meta.pos;
+        if delta.length() < SNAP {
+            meta.pos = target;
+        } else {
+            meta.pos += delta * step;
+            moved = true;
+        }
+        meta.vel = Vec2::ZERO;
+    }
+    moved
+}
+
+/// A cell of a [`QuadTree`].
+#[derive(Clone, Debug)]
+struct Cell {
+    /// Square bounds of the cell.
+    bounds: Rect,
+    /// Total mass of the bodies in the cell.
+    mass: f32,
+    /// Sum of the positions of the bodies in the cell, weighted by mass.
+    moment: Vec2,
+    /// Index of the first of four children, if the cell is split.
+    children: Option<usize>,
+}
+
+impl Cell {
+    fn new(bounds: Rect) -> Self {
+        Self {
+            bounds,
+            mass: 0.0,
+            moment: Vec2::ZERO,
+            children: None,
+        }
+    }
+
+    /// Mass-weighted centroid of the bodies in the cell.
+    fn centroid(&self) -> Pos2 {
+        (self.moment / self.mass).to_pos2()
+    }
+
+    /// Which child a `pos` within the cell falls in.
+    fn quadrant(&self, pos: Pos2) -> usize {
+        let center = self.bounds.center();
+        (pos.x >= center.x) as usize + 2 * (pos.y >= center.y) as usize
+    }
+}
+
+/// Barnes–Hut quadtree of point masses. Each cell knows the total mass and
+/// mass-weighted centroid of the bodies within it, so that the pull of a
+/// distant cell can stand in for the bodies inside it. This makes the field
+/// at a point O(log n) rather than O(n) to compute.
+#[derive(Clone, Debug)]
+pub struct QuadTree {
+    /// The root is first. Children are stored in fours.
+    cells: Vec<Cell>,
+}
+
+impl QuadTree {
+    /// Build a tree from `bodies`, pairs of position and mass. Bodies with no
+    /// mass are ignored.
+    pub fn new(bodies: &[(Pos2, f32)]) -> Self {
+        let bounds = bodies.iter().fold(Rect::NOTHING, |bounds, &(pos, _)| {
+            bounds.union(Rect::from_min_max(pos, pos))
+        });
+        // Cells are square so that their width is their size in every
+        // direction.
+        let side = bounds.width().max(bounds.height()).max(1.0);
+        let min = if bounds.is_finite() {
+            bounds.min
+        } else {
+            Pos2::ZERO
+        };
+        let mut tree = Self {
+            cells: vec![Cell::new(Rect::from_min_size(min, Vec2::splat(side)))],
+        };
+        for &(pos, mass) in bodies {
+            if mass > 0.0 {
+                tree.insert(pos, mass);
+            }
+        }
+        tree
+    }
+
+    fn insert(&mut self, pos: Pos2, mass: f32) {
+        let mut index = 0;
+        let mut depth = 0;
+        loop {
+            let cell = &mut self.cells[index];
+            if let Some(first) = cell.children {
+                cell.mass += mass;
+                cell.moment += pos.to_vec2() * mass;
+                index = first + cell.quadrant(pos);
+                depth += 1;
+                continue;
+            }
+            if cell.mass == 0.0 || depth >= MAX_DEPTH {
+                cell.mass += mass;
+                cell.moment += pos.to_vec2() * mass;
+                return;
+            }
+
+            // Split the leaf, moving its body into a child, and try again.
+            let (bounds, existing) = (cell.bounds, cell.centroid());
+            let moved = (cell.quadrant(existing), cell.mass, cell.moment);
+            let first = self.cells.len();
+            self.cells[index].children = Some(first);
+            let center = bounds.center();
+            self.cells.extend(
+                [
+                    Rect::from_two_pos(bounds.min, center),
+                    Rect::from_two_pos(bounds.right_top(), center),
+                    Rect::from_two_pos(bounds.left_bottom(), center),
+                    Rect::from_two_pos(bounds.max, center),
+                ]
+                .map(Cell::new),
+            );
+            let child = &mut self.cells[first + moved.0];
+            child.mass = moved.1;
+            child.moment = moved.2;
+        }
+    }
+
+    /// Total mass of all bodies.
+    pub fn mass(&self) -> f32 {
+        self.cells[0].mass
+    }
+
+    /// Mass-weighted centroid of all bodies, if there are any.
+    pub fn centroid(&self) -> Option<Pos2> {
+        (self.mass() > 0.0).then(|| self.cells[0].centroid())
+    }
+
+    /// Inverse square field at `pos` pointing away from the bodies, scaled by
+    /// their mass. The field is softened so that it is bounded near bodies. A
+    /// body exactly at `pos` is ignored so that bodies don't push themselves.
+    /// `theta` trades accuracy for speed. See [`THETA`].
+    pub fn field(&self, pos: Pos2, theta: f32) -> Vec2 {
+        let mut field = Vec2::ZERO;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let cell = &self.cells[index];
+            if cell.mass == 0.0 {
+                continue;
+            }
+            let delta = pos - cell.centroid();
+            let distance_sq = delta.length_sq();
+            match cell.children {
+                Some(first)
+                    if cell.bounds.width().powi(2)
+                        >= theta.powi(2) * distance_sq =>
+                {
+                    stack.extend(first..first + 4);
+                }
+                _ if distance_sq == 0.0 => {}
+                _ => {
+                    field += delta.normalized() * cell.mass
+                        / (distance_sq + SOFTENING.powi(2));
+                }
+            }
+        }
+        field
+    }
+}
+
+/// One step of a force-directed layout of the tree under `root`. Every node
+/// repels every other with inverse square force, computed with a
+/// [`QuadTree`]. Edges pull nodes and their children together like springs,
+/// and all nodes are attracted to the mass-weighted centroid of the tree.
+/// Pinned nodes push and pull the others but are fixed in place.
+///
+/// Returns true if any node moved, in which case a redraw is needed.
+pub fn force_directed(
+    root: &mut Node<Meta>,
+    repulsion: f32,
+    attraction: f32,
+    gravity: f32,
+    time_step: f32,
+) -> bool {
+    let metas = preorder_mut(root);
+    let bodies: Vec<(Pos2, f32)> = metas
+        .iter()
+        .enumerate()
+        .map(|(i, (_, meta))| {
+            let jitter = Vec2::angled(i as f32) * JITTER;
+            (
+                meta.pos + meta.size / 2.0 + jitter,
+                meta.mass().max(MIN_MASS),
+            )
+        })
+        .collect();
+    let tree = QuadTree::new(&bodies);
+    let centroid = tree.centroid().unwrap_or_default();
+    let total = tree.mass();
+
+    let mut forces: Vec<Vec2> = bodies
+        .iter()
+        .map(|&(pos, mass)| {
+            let to_centroid = centroid - pos;
+            repulsion * mass * tree.field(pos, THETA)
+                + gravity * mass * (total - mass) * to_centroid.normalized()
+                    / (to_centroid.length_sq() + SOFTENING.powi(2))
+        })
+        .collect();
+    for (child, &(parent, _)) in metas.iter().enumerate() {
+        if let Some(parent) = parent {
+            let (from, from_mass) = bodies[child];
+            let (to, to_mass) = bodies[parent];
+            let delta = to - from;
+            let force = attraction * from_mass * to_mass * delta / EDGE_SCALE;
+            forces[child] += force;
+            forces[parent] -= force;
+        }
+    }
+
+    let mut moved = false;
+    for ((_, meta), force) in metas.into_iter().zip(forces) {
+        if meta.pinned {
+            meta.vel = Vec2::ZERO;
+            continue;
+        }
+        meta.vel = (meta.vel * (1.0 - DAMPING) + force * time_step)
+            .clamp(Vec2::splat(-MAX_SPEED), Vec2::splat(MAX_SPEED));
+        if meta.vel.length() < REST_SPEED {
+            meta.vel = Vec2::ZERO;
+        } else {
+            meta.pos += meta.vel;
+            moved = true;
+        }
+    }
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A root with two children, the first of which has three children. All
+    /// nodes are 100 by 50.
+    fn tree() -> Node<Meta> {
+        let mut root = Node::<Meta>::default();
+        for _ in 0..2 {
+            root.add_child(Node::default());
+        }
+        for _ in 0..3 {
+            root.children[0].add_child(Node::default());
+        }
+        let mut stack = vec![&mut root];
+        while let Some(node) = stack.pop() {
+            node.meta.size = Vec2::new(100.0, 50.0);
+            stack.extend(node.children.iter_mut());
+        }
+        root
+    }
+
+    #[test]
+    fn test_tidy() {
+        let root = tree();
+        let positions = tidy(&root, Direction::TopDown, 10.0, 20.0);
+        // root, a, a0, a1, a2, b
+        assert_eq!(positions.len(), 6);
+        assert_eq!(positions[0], root.meta.pos);
+        // Layers
+        assert_eq!(positions[1].y, 70.0);
+        assert_eq!(positions[2].y, 140.0);
+        // Grandchildren are side by side, centered under their parent.
+        assert_eq!(positions[3].x - positions[2].x, 110.0);
+        assert_eq!(positions[1].x, positions[3].x);
+        // The second child clears the first child's subtree, not just the
+        // first child, as the subtrees' contours don't overlap below.
+        assert_eq!(positions[5].x - positions[1].x, 110.0);
+        // The root is centered over its children.
+        assert_eq!(positions[0].x, (positions[1].x + positions[5].x) / 2.0);
+
+        let positions = tidy(&root, Direction::LeftRight, 10.0, 20.0);
+        assert_eq!(positions[1].x, 120.0);
+        assert_eq!(positions[3].y - positions[2].y, 60.0);
+    }
+
+    #[test]
+    fn test_radial() {
+        let root = tree();
+        let positions = radial(&root, 50.0);
+        assert_eq!(positions[0], root.meta.pos);
+        let center = |i: usize| positions[i] + Vec2::new(50.0, 25.0);
+        let distance = |i: usize| center(i).distance(center(0));
+        // Siblings share a ring, and grandchildren are further out.
+        assert!((distance(1) - distance(5)).abs() < 0.01);
+        assert!(distance(2) > distance(1) + 50.0);
+        assert!((distance(2) - distance(4)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_animate() {
+        let mut root = tree();
+        let targets = tidy(&root, Direction::TopDown, 10.0, 20.0);
+        root.children[1].meta.pinned = true;
+        assert!(animate(&mut root, &targets, 0.05));
+        assert_ne!(root.children[0].meta.pos, targets[1]);
+        while animate(&mut root, &targets, 0.05) {}
+        assert_eq!(root.children[0].meta.pos, targets[1]);
+        // Pinned nodes stay put.
+        assert_eq!(root.children[1].meta.pos, Pos2::ZERO);
+    }
+
+    /// Bodies on a sunflower spiral, with varying masses.
+    fn bodies(count: usize) -> Vec<(Pos2, f32)> {
+        (0..count)
+            .map(|i| {
+                let angle = i as f32 * 2.4;
+                let radius = 100.0 * (i as f32).sqrt();
+                let pos = Pos2::new(angle.cos(), angle.sin()) * radius;
+                (pos, 1.0 + (i % 7) as f32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_quadtree() {
+        let bodies = bodies(500);
+        let tree = QuadTree::new(&bodies);
+
+        // The centroid is weighted by mass.
+        let mass: f32 = bodies.iter().map(|&(_, m)| m).sum();
+        let centroid = bodies
+            .iter()
+            .fold(Vec2::ZERO, |sum, &(p, m)| sum + p.to_vec2() * m)
+            / mass;
+        assert!((tree.mass() - mass).abs() < 0.01);
+        assert!(tree.centroid().unwrap().distance(centroid.to_pos2()) < 0.01);
+
+        // With theta of zero the field is exact, and otherwise close. As the
+        // field can cancel out, error is relative to the sum of the
+        // magnitudes of its parts.
+        for &(pos, _) in bodies.iter().step_by(50) {
+            let (brute, magnitude) = bodies
+                .iter()
+                .filter(|&&(p, _)| p != pos)
+                .fold((Vec2::ZERO, 0.0), |(field, magnitude), &(p, m)| {
+                    let part = (pos - p).normalized() * m
+                        / ((pos - p).length_sq() + SOFTENING.powi(2));
+                    (field + part, magnitude + part.length())
+                });
+            let exact = tree.field(pos, 0.0);
+            assert!((exact - brute).length() <= magnitude * 1e-4);
+            let approx = tree.field(pos, THETA);
+            assert!((approx - brute).length() <= magnitude * 0.05);
+        }
+
+        // Bodies at the same position are merged rather than split forever.
+        let tree = QuadTree::new(&[(Pos2::ZERO, 1.0); 3]);
+        assert_eq!(tree.mass(), 3.0);
+        assert_eq!(tree.field(Pos2::ZERO, THETA), Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_force_directed() {
+        // Cousins start on top of each other and are pushed apart, which
+        // repulsion between siblings alone would not do. So do the rest of
+        // the nodes, which all start in the same place.
+        let mut root = tree();
+        root.children[1].add_child(Node::default());
+        root.children[1].children[0].meta.size = Vec2::new(100.0, 50.0);
+        root.children[0].children[0].meta.pos = Pos2::new(0.0, 200.0);
+        root.children[1].children[0].meta.pos = Pos2::new(1.0, 200.0);
+        root.children[1].meta.pinned = true;
+        for _ in 0..10 {
+            assert!(force_directed(&mut root, 125.0, 2.5, 1.0, 1.0 / 30.0));
+        }
+        // Pinned nodes are anchors.
+        assert_eq!(root.children[1].meta.pos, Pos2::ZERO);
+        let a = root.children[0].children[0].meta.pos;
+        let b = root.children[1].children[0].meta.pos;
+        assert!(a.distance(b) > 1.0);
+        let a = root.children[0].meta.pos;
+        let b = root.children[1].meta.pos;
+        assert!(a.distance(b) > 1.0);
+    }
+}
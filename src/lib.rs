@@ -19,10 +19,28 @@ pub(crate) mod openai;
 #[cfg(all(feature = "drama_llama", not(target_arch = "wasm32")))]
 pub(crate) mod drama_llama;
 
+/// Contribution and attribution [`Report`]s for a [`Story`].
+pub mod attribution;
+/// Pannable, zoomable [`Canvas`] for the node view.
+#[cfg(feature = "gui")]
+pub mod canvas;
 /// Crate-wide constants.
 pub mod consts;
+/// Fine-tuning dataset export [`Options`].
+pub mod dataset;
+/// Word-level [`diff`] and side-by-side [`Comparison`] of story branches.
+pub mod diff;
+/// Tidy tree, radial and force-directed [`layout`]s of [`Node`]s, with a
+/// Barnes–Hut [`QuadTree`] for the forces.
+#[cfg(feature = "gui")]
+pub mod layout;
+/// Directory [`Library`] of stories, one file per story.
+#[cfg(not(target_arch = "wasm32"))]
+pub mod library;
 /// Contains [`Node`] and associated types such as [`Meta`].
 pub mod node;
+/// Full-text [`search`] of [`Story`] nodes.
+pub mod search;
 /// Contains a branching [`Story`] (a tree of [`Node`]s).
 pub mod story;
 
@@ -0,0 +1,576 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::story::Story;
+
+/// Name of the index file in the library directory.
+const INDEX: &str = "index.json";
+/// Name of the settings file in the library directory.
+const SETTINGS: &str = "settings.json";
+/// Subdirectory containing the story files.
+const STORIES: &str = "stories";
+/// Subdirectory containing copies of stories with changes that haven't been
+/// saved to the library yet.
+const RECOVERY: &str = "recovery";
+/// File that exists while Weave is running. If it exists at startup, the last
+/// session ended uncleanly.
+const LOCK: &str = "session.lock";
+
+/// An error reading or writing a [`Library`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to access `{}` because: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to parse `{}` because: {source}", path.display())]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+static_assertions::assert_impl_all!(Error: Send, Sync);
+
+/// A story in the [`Index`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Name of the story's file in the stories subdirectory.
+    pub file: String,
+    /// Title of the story when it was saved.
+    pub title: String,
+    /// JSON file the story is saved to outside the library, if any.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// [`Story::fingerprint`] when the story was last saved to its `path`, so
+    /// it isn't shown as changed when loaded unless it is.
+    #[serde(default)]
+    pub saved: Option<u64>,
+}
+
+/// Which story files are in a [`Library`], and in what order.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Index {
+    pub stories: Vec<Entry>,
+    pub trash: Vec<Entry>,
+}
+
+/// Stories, and the trash, loaded from a [`Library`]. Stories that failed to
+/// load are skipped and their errors collected so one bad file doesn't lose
+/// the rest.
+#[derive(Default)]
+pub struct Loaded {
+    pub stories: Vec<Story>,
+    pub trash: Vec<Story>,
+    pub errors: Vec<Error>,
+}
+
+/// A directory of stories, one JSON file per story, with an [`Index`] of the
+/// stories and the trash. Files are written atomically so a crash while
+/// saving never leaves a half-written file. Story files can be copied or
+/// backed up while Weave isn't running.
+///
+/// Between saves, changed stories can be written to a recovery directory so
+/// they can be restored if Weave exits uncleanly.
+#[derive(Debug)]
+pub struct Library {
+    dir: PathBuf,
+    /// Story files loaded or saved by this library, with the
+    /// [`Story::file_hash`] of their contents. Only these are removed when
+    /// their stories are deleted, so other files are left alone.
+    written: HashMap<String, u64>,
+    /// File hashes of the stories in the recovery directory.
+    recovered: HashMap<String, u64>,
+}
+
+static_assertions::assert_impl_all!(Library: Send, Sync);
+
+impl Library {
+    /// A library in `dir`. Nothing is read or written until the library is
+    /// loaded or saved.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            written: HashMap::new(),
+            recovered: HashMap::new(),
+        }
+    }
+
+    /// The library directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns true if the library has been saved before.
+    pub fn exists(&self) -> bool {
+        self.dir.join(INDEX).is_file()
+    }
+
+    /// Read the index and the stories it lists.
+    pub fn load(&mut self) -> Result<Loaded, Error> {
+        let index: Index = read_json(&self.dir.join(INDEX))?;
+        let mut loaded = Loaded::default();
+        for (entries, stories) in [
+            (&index.stories, &mut loaded.stories),
+            (&index.trash, &mut loaded.trash),
+        ] {
+            for entry in entries {
+                let path = self.dir.join(STORIES).join(&entry.file);
+                match read_json::<Story>(&path) {
+                    Ok(mut story) => {
+                        self.written
+                            .insert(entry.file.clone(), story.file_hash());
+                        story.library_file = Some(entry.file.clone());
+                        match &entry.path {
+                            Some(path) => {
+                                story.set_path(path);
+                                if let Some(fingerprint) = entry.saved {
+                                    story.mark_saved(fingerprint);
+                                }
+                            }
+                            None => story.mark_saved(story.fingerprint()),
+                        }
+                        stories.push(story);
+                    }
+                    Err(e) => loaded.errors.push(e),
+                }
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// Write the `stories`, the `trash` and the index. Stories that aren't in
+    /// the library yet are given a file, and only those that have changed are
+    /// written, layout included. Files of stories that were deleted since the
+    /// last load or save are removed. Stories without a [`Story::path`] of their own are marked
+    /// saved.
+    pub fn save(
+        &mut self,
+        stories: &mut [Story],
+        trash: &mut [Story],
+    ) -> Result<(), Error> {
+        let dir = self.dir.join(STORIES);
+        std::fs::create_dir_all(&dir).map_err(|source| Error::Io {
+            path: dir.clone(),
+            source,
+        })?;
+
+        let mut index = Index::default();
+        let mut files = HashSet::new();
+        for (stories, entries) in
+            [(stories, &mut index.stories), (trash, &mut index.trash)]
+        {
+            for story in stories.iter_mut() {
+                // Two stories can't share a file.
+                if story
+                    .library_file
+                    .as_ref()
+                    .is_some_and(|file| files.contains(file))
+                {
+                    story.library_file = None;
+                }
+                let file = library_file(story).to_string();
+                let hash = story.file_hash();
+                if self.written.get(&file) != Some(&hash) {
+                    write_json(&dir.join(&file), story)?;
+                    self.written.insert(file.clone(), hash);
+                }
+                if story.path().is_none() {
+                    story.mark_saved(story.fingerprint());
+                }
+                files.insert(file.clone());
+                entries.push(Entry {
+                    file,
+                    title: story.title.clone(),
+                    path: story.path().map(Path::to_path_buf),
+                    saved: story.path().and(story.saved_fingerprint()),
+                });
+            }
+        }
+        write_json(&self.dir.join(INDEX), &index)?;
+
+        // The index no longer refers to deleted stories, so it's safe to
+        // remove their files.
+        self.written.retain(|file, _| {
+            if files.contains(file) {
+                return true;
+            }
+            let path = dir.join(file);
+            if let Err(e) = std::fs::remove_file(&path) {
+                log::warn!("Failed to remove `{}`: {}", path.display(), e);
+            }
+            false
+        });
+
+        Ok(())
+    }
+
+    /// Write copies of the `stories` that have unsaved changes, and have changed
+    /// since they were saved to the library or last written here, to the
+    /// recovery directory. Arranging or navigating a story alone doesn't need
+    /// recovering. Returns the number written.
+    pub fn save_recovery(
+        &mut self,
+        stories: &mut [Story],
+    ) -> Result<usize, Error> {
+        let dir = self.dir.join(RECOVERY);
+        let mut count = 0;
+        for story in stories.iter_mut() {
+            if !story.check_dirty() {
+                continue;
+            }
+            let file = library_file(story).to_string();
+            let hash = story.file_hash();
+            if self.written.get(&file) == Some(&hash)
+                || self.recovered.get(&file) == Some(&hash)
+            {
+                continue;
+            }
+            write_json(&dir.join(&file), story)?;
+            self.recovered.insert(file, hash);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Read the stories in the recovery directory. Each has the library file
+    /// of the story it's a copy of, if that is in the library.
+    pub fn load_recovery(&self) -> Result<Loaded, Error> {
+        let dir = self.dir.join(RECOVERY);
+        let mut loaded = Loaded::default();
+        for path in json_files(&dir)? {
+            match read_json::<Story>(&path) {
+                Ok(mut story) => {
+                    story.library_file = path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned());
+                    loaded.stories.push(story);
+                }
+                Err(e) => loaded.errors.push(e),
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// Remove the stories in the recovery directory. Do this once the library
+    /// has been saved, or recovery is declined.
+    pub fn clear_recovery(&mut self) -> Result<(), Error> {
+        self.recovered.clear();
+        for path in json_files(&self.dir.join(RECOVERY))? {
+            std::fs::remove_file(&path)
+                .map_err(|source| Error::Io { path, source })?;
+        }
+        Ok(())
+    }
+
+    /// Mark the start of a session. Returns true if the previous session
+    /// didn't [`Library::end_session`], in which case there may be stories
+    /// to recover.
+    pub fn begin_session(&self) -> Result<bool, Error> {
+        let path = self.dir.join(LOCK);
+        let unclean = path.exists();
+        write_atomic(&path, std::process::id().to_string().as_bytes())
+            .map_err(|source| Error::Io { path, source })?;
+        Ok(unclean)
+    }
+
+    /// Mark the end of a clean session.
+    pub fn end_session(&self) -> Result<(), Error> {
+        let path = self.dir.join(LOCK);
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(Error::Io { path, source: e })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Read the settings, if they've been saved.
+    pub fn load_settings<T>(&self) -> Result<Option<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let path = self.dir.join(SETTINGS);
+        if path.is_file() {
+            read_json(&path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Write the `settings`.
+    pub fn save_settings<T>(&self, settings: &T) -> Result<(), Error>
+    where
+        T: Serialize,
+    {
+        write_json(&self.dir.join(SETTINGS), settings)
+    }
+}
+
+/// The story's file name in the library, which is chosen if it doesn't have
+/// one yet.
+fn library_file(story: &mut Story) -> &str {
+    story
+        .library_file
+        .get_or_insert_with(|| format!("{}.json", uuid::Uuid::new_v4()))
+}
+
+/// JSON files in `dir`. A missing directory has none.
+fn json_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Vec::new())
+        }
+        Err(source) => {
+            return Err(Error::Io {
+                path: dir.to_path_buf(),
+                source,
+            })
+        }
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|source| Error::Io {
+                path: dir.to_path_buf(),
+                source,
+            })?
+            .path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Read and parse a JSON file.
+fn read_json<T>(path: &Path) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    let text = std::fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    serde_json::from_str(&text).map_err(|source| Error::Json {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Serialize `value` and [`write_atomic`]ally to a JSON file.
+fn write_json<T>(path: &Path, value: &T) -> Result<(), Error>
+where
+    T: Serialize + ?Sized,
+{
+    let json = serde_json::to_string(value).map_err(|source| Error::Json {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    write_atomic(path, json.as_bytes()).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Write `contents` to a temporary file next to `path`, then rename it over
+/// `path`. Readers see either the old file or the new one, never a partial
+/// write. Missing parent directories are created.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = std::fs::File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh, empty directory for a test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "weave-{}-{}",
+            name,
+            uuid::Uuid::new_v4()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = temp_dir("library");
+        let mut library = Library::new(&dir);
+        assert!(!library.exists());
+
+        let mut stories = vec![
+            Story::new("One".to_string(), "Alice".to_string()),
+            Story::new("Two".to_string(), "Bob".to_string()),
+        ];
+        stories[0].add_paragraph("Alice", ["Once upon a time"]);
+        let mut trash = vec![Story::new("Old".to_string(), "Eve".to_string())];
+        library.save(&mut stories, &mut trash).unwrap();
+        assert!(library.exists());
+        let file = stories[0].library_file.clone().unwrap();
+
+        let mut reopened = Library::new(&dir);
+        let loaded = reopened.load().unwrap();
+        assert!(loaded.errors.is_empty());
+        let titles: Vec<_> =
+            loaded.stories.iter().map(|s| s.title.as_str()).collect();
+        assert_eq!(titles, ["One", "Two"]);
+        assert_eq!(loaded.trash[0].title, "Old");
+        assert_eq!(loaded.stories[0].head().text, "Once upon a time");
+        assert_eq!(loaded.stories[0].library_file.as_ref(), Some(&file));
+
+        // A corrupt story file only loses that story.
+        let bad = stories[1].library_file.clone().unwrap();
+        std::fs::write(dir.join(STORIES).join(&bad), "{").unwrap();
+        let loaded = Library::new(&dir).load().unwrap();
+        assert_eq!(loaded.stories.len(), 1);
+        assert_eq!(loaded.trash.len(), 1);
+        assert!(matches!(loaded.errors[..], [Error::Json { .. }]));
+
+        // Emptying the trash removes the story's file, but not strangers.
+        let stranger = dir.join(STORIES).join("stranger.json");
+        std::fs::write(&stranger, "{}").unwrap();
+        let old = trash[0].library_file.clone().unwrap();
+        reopened.save(&mut stories, &mut []).unwrap();
+        assert!(!dir.join(STORIES).join(old).exists());
+        assert!(dir.join(STORIES).join(file).exists());
+        assert!(stranger.exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_layout() {
+        let dir = temp_dir("layout");
+        let mut library = Library::new(&dir);
+        let mut stories =
+            vec![Story::new("One".to_string(), "Alice".to_string())];
+        stories[0].add_paragraph("Alice", ["a"]);
+        stories[0].add_paragraph("Alice", ["b"]);
+        library.save(&mut stories, &mut []).unwrap();
+
+        // Moving a node and navigating aren't edits, but they are saved.
+        let pos = egui::Pos2::new(100.0, 50.0);
+        stories[0].node_mut(&[0]).unwrap().meta.pos = pos;
+        stories[0].set_active_path(vec![0]);
+        assert!(!stories[0].check_dirty());
+        library.save(&mut stories, &mut []).unwrap();
+
+        let loaded = Library::new(&dir).load().unwrap();
+        let mut story = loaded.stories.into_iter().next().unwrap();
+        assert_eq!(story.active_path(), &[0]);
+        assert_eq!(story.node_mut(&[0]).unwrap().meta.pos, pos);
+
+        // Pinning a node is an edit, and the pin is saved too.
+        stories[0].node_mut(&[0, 0]).unwrap().meta.pinned = true;
+        assert!(stories[0].check_dirty());
+        library.save(&mut stories, &mut []).unwrap();
+        assert!(!stories[0].is_dirty());
+        let loaded = Library::new(&dir).load().unwrap();
+        let mut story = loaded.stories.into_iter().next().unwrap();
+        assert!(story.node_mut(&[0, 0]).unwrap().meta.pinned);
+        assert_eq!(story.node_mut(&[0]).unwrap().meta.pos, pos);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_saved_in_place() {
+        let dir = temp_dir("in-place");
+        let mut library = Library::new(&dir);
+        let mut stories = vec![
+            Story::new("One".to_string(), "Alice".to_string()),
+            Story::new("Two".to_string(), "Bob".to_string()),
+        ];
+        // Both were saved to files of their own, but "Two" changed since.
+        for story in stories.iter_mut() {
+            story.set_path(dir.join(format!("{}.json", story.title)));
+            story.mark_saved(story.fingerprint());
+        }
+        stories[1].add_paragraph("Bob", ["changed"]);
+        library.save(&mut stories, &mut []).unwrap();
+
+        let mut loaded = Library::new(&dir).load().unwrap();
+        assert!(!loaded.stories[0].check_dirty());
+        assert!(loaded.stories[1].check_dirty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_recovery() {
+        let dir = temp_dir("recovery");
+        let mut library = Library::new(&dir);
+        assert!(!library.begin_session().unwrap());
+
+        let mut stories =
+            vec![Story::new("One".to_string(), "Alice".to_string())];
+        library.save(&mut stories, &mut []).unwrap();
+        assert!(!stories[0].is_dirty());
+        // Unchanged stories don't need recovering.
+        assert_eq!(library.save_recovery(&mut stories).unwrap(), 0);
+
+        stories[0].add_paragraph("Alice", ["changed"]);
+        stories.push(Story::new("Two".to_string(), "Bob".to_string()));
+        assert_eq!(library.save_recovery(&mut stories).unwrap(), 2);
+        assert_eq!(library.save_recovery(&mut stories).unwrap(), 0);
+
+        // The session didn't end, so the next one recovers the changes.
+        let mut next = Library::new(&dir);
+        assert!(next.begin_session().unwrap());
+        let loaded = next.load().unwrap();
+        assert_eq!(loaded.stories[0].head().text, "");
+        let recovered = next.load_recovery().unwrap();
+        assert_eq!(recovered.stories.len(), 2);
+        let one = recovered
+            .stories
+            .iter()
+            .find(|story| story.title == "One")
+            .unwrap();
+        assert_eq!(one.head().text, "changed");
+        assert_eq!(one.library_file, stories[0].library_file);
+
+        next.clear_recovery().unwrap();
+        assert!(next.load_recovery().unwrap().stories.is_empty());
+        next.end_session().unwrap();
+        assert!(!Library::new(&dir).begin_session().unwrap());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = temp_dir("atomic");
+        let path = dir.join("nested").join("file.json");
+        write_atomic(&path, b"one").unwrap();
+        write_atomic(&path, b"two").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "two");
+        // The temporary file is renamed away.
+        assert_eq!(
+            std::fs::read_dir(path.parent().unwrap()).unwrap().count(),
+            1
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
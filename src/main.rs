@@ -36,19 +36,31 @@ fn load_icon() -> std::sync::Arc<IconData> {
 fn main() {
     use eframe::egui::Visuals;
     use egui::ViewportBuilder;
-    use weave_writer::app::App;
+    use weave_writer::app::{App, APP_ID};
 
     env_logger::init();
 
+    // Story files to open, for example from a file manager's "open with" or a
+    // script. Anything that looks like a flag is ignored.
+    let files: Vec<std::path::PathBuf> = std::env::args_os()
+        .skip(1)
+        .filter(|arg| !arg.to_string_lossy().starts_with('-'))
+        .map(Into::into)
+        .collect();
+
     let mut native_options = eframe::NativeOptions::default();
     native_options.viewport = ViewportBuilder::default().with_icon(load_icon());
 
     eframe::run_native(
-        "Weave",
+        APP_ID,
         native_options,
-        Box::new(|cc| {
+        Box::new(move |cc| {
             cc.egui_ctx.set_visuals(Visuals::dark());
-            Box::new(App::new(cc))
+            let mut app = App::new(cc);
+            for path in &files {
+                app.open(path);
+            }
+            Box::new(app)
         }),
     )
     .expect("Failed to run native example");
@@ -1,56 +1,58 @@
-use egui::Pos2;
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "gui")]
+use crate::layout::Direction;
+use crate::story::AuthorProfile;
+
 /// A piece of the text. Generally representing a detokenized token.
 // In the future this may contain per-piece metadata.
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Piece {
     /// End index of the piece (start is the end of the previous piece).
     pub end: usize,
 }
 
-/// Damping factor for the force-directed layout.
-const DAMPING: f32 = 0.10;
-/// Boundary damping factor when nodes hit the boundaries and bounce back.
-const BOUNDARY_DAMPING: f32 = 0.5;
 /// Mass divisor for the force-directed layout.
 const MASS_DIVISOR: f32 = 1000.0;
-/// Padding for the bounding rectangle of the node. Also the max velocity.
+/// Padding for the bounding rectangle of the node.
 const PADDING: f32 = 32.0;
-/// Ratio of local to global centroid and mass. A ratio of 5 means that the
-/// nodes are 5 times more attracted to the local centroid than the global
-/// centroid. This also controls the repulsion from the parent node.
-const LOCAL_GLOBAL_RATIO: f32 = 5.0;
+/// Width of a node on the canvas.
+const NODE_WIDTH: f32 = 320.0;
+/// Width of a node preview on the canvas.
+const PREVIEW_WIDTH: f32 = 200.0;
+/// Number of characters of text in a node preview.
+const PREVIEW_CHARS: usize = 120;
 
 static_assertions::assert_impl_all!(Piece: Send, Sync);
 
-/// Constrain one rect `a` so it's inside another rect `b`.
-fn constrain(mut a: egui::Rect, b: egui::Rect) -> egui::Rect {
-    if a.left() < b.left() {
-        a = a.translate(egui::Vec2::new(b.left() - a.left(), 0.0));
-    }
-    if a.right() > b.right() {
-        a = a.translate(egui::Vec2::new(b.right() - a.right(), 0.0));
-    }
-    if a.top() < b.top() {
-        a = a.translate(egui::Vec2::new(0.0, b.top() - a.top()));
-    }
-    if a.bottom() > b.bottom() {
-        a = a.translate(egui::Vec2::new(0.0, b.bottom() - a.bottom()));
-    }
-
-    a
-}
+/// Author id. This indexes the authors of a [`Story`]. Stories saved with the
+/// older `u8` ids deserialize as-is.
+///
+/// [`Story`]: crate::story::Story
+pub type AuthorIndex = u16;
 
 /// Node data. Contains a paragraph within a story tree.
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct Node<T> {
     /// Author id.
-    pub author_id: u8,
+    pub author_id: AuthorIndex,
     /// The text of the paragraph.
     pub text: String,
     /// Piece indices.
     pub pieces: Vec<Piece>,
+    /// Rating from 1 to [`MAX_RATING`], if rated.
+    #[serde(default)]
+    pub rating: Option<u8>,
+    /// Editorial flags.
+    #[serde(default)]
+    pub flags: Flags,
+    /// Tags, such as "chapter-2" or "canon".
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Margin notes. These are never part of the story text, so they are not
+    /// included in prompts and are only exported if asked for.
+    #[serde(default)]
+    pub notes: String,
     /// The children of this node.
     pub children: Vec<Node<T>>,
     /// Metadata.
@@ -60,6 +62,182 @@ pub struct Node<T> {
 
 static_assertions::assert_impl_all!(Node<Meta>: Send, Sync);
 
+/// Which nodes to show. The default filter matches every node. A node must
+/// pass every part of the filter that is set.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Filter {
+    /// Only show nodes containing this text, ignoring case. Surrounding
+    /// whitespace is ignored.
+    pub text: String,
+    /// Only show nodes by this author.
+    pub author: Option<AuthorIndex>,
+    /// Only show nodes with this tag.
+    pub tag: Option<String>,
+    /// Only show nodes rated at least this.
+    pub min_rating: Option<u8>,
+}
+
+impl Filter {
+    /// Returns true if the filter matches every node.
+    pub fn is_empty(&self) -> bool {
+        self.text.trim().is_empty()
+            && self.author.is_none()
+            && self.tag.is_none()
+            && self.min_rating.is_none()
+    }
+
+    /// Prepare the filter for matching many nodes, so the text is only
+    /// lowercased once.
+    pub fn matcher(&self) -> Matcher<'_> {
+        Matcher {
+            filter: self,
+            text: self.text.trim().to_lowercase(),
+        }
+    }
+
+    /// Returns true if the `node` passes the filter. To match many nodes, use
+    /// a [`Filter::matcher`].
+    pub fn matches<T>(&self, node: &Node<T>) -> bool {
+        self.matcher().matches(node)
+    }
+
+    /// Returns true if the `node` or any of its descendants pass the filter.
+    pub fn matches_subtree<T>(&self, node: &Node<T>) -> bool {
+        let matcher = self.matcher();
+        self.is_empty() || node.iter_depth_first().any(|n| matcher.matches(n))
+    }
+}
+
+/// A [`Filter`] prepared for matching many nodes.
+pub struct Matcher<'a> {
+    filter: &'a Filter,
+    /// Lowercased text to find.
+    text: String,
+}
+
+impl Matcher<'_> {
+    /// Returns true if the `node` passes the filter.
+    pub fn matches<T>(&self, node: &Node<T>) -> bool {
+        let Filter {
+            author,
+            tag,
+            min_rating,
+            ..
+        } = self.filter;
+        tag.as_ref()
+            .map_or(true, |tag| node.tags.iter().any(|t| t == tag))
+            && author.map_or(true, |author| node.author_id == author)
+            && min_rating
+                .map_or(true, |min| node.rating.is_some_and(|r| r >= min))
+            && (self.text.is_empty()
+                || node.text.to_lowercase().contains(&self.text))
+    }
+
+    /// Find the nodes in the tree rooted at `node` that pass the filter, and
+    /// those shown because they or any of their descendants do, in one pass.
+    #[cfg(feature = "gui")]
+    pub fn visible(&self, node: &Node<Meta>) -> Visible {
+        let mut visible = Visible::default();
+        self.visit(node, &mut visible);
+        visible
+    }
+
+    /// Add the `node` and its descendants to `visible`, children first.
+    /// Returns true if the `node` is shown.
+    #[cfg(feature = "gui")]
+    fn visit(&self, node: &Node<Meta>, visible: &mut Visible) -> bool {
+        let matched = self.matches(node);
+        if matched {
+            visible.matched.insert(node.meta.id);
+        }
+        let mut shown = matched;
+        for child in node.children.iter() {
+            // Every child is visited, even once one is shown.
+            shown |= self.visit(child, visible);
+        }
+        if shown {
+            visible.shown.insert(node.meta.id);
+        }
+        shown
+    }
+}
+
+/// Ids of the nodes in a tree that pass a [`Filter`], and of those shown
+/// because they or any of their descendants do. See [`Matcher::visible`].
+#[cfg(feature = "gui")]
+#[derive(Default)]
+pub struct Visible {
+    matched: std::collections::HashSet<u128>,
+    shown: std::collections::HashSet<u128>,
+}
+
+/// How to draw a tree of nodes: their layout, the authors whose initials are
+/// in their titles, and which of them to show.
+#[cfg(feature = "gui")]
+#[derive(Clone, Copy)]
+pub struct View<'a> {
+    pub layout: Layout,
+    /// Authors by [`AuthorIndex`].
+    pub authors: &'a [AuthorProfile],
+    pub filter: &'a Filter,
+    /// The nodes shown in the tree view, if filtering. Found by [`Node::draw`]
+    /// once per frame.
+    visible: Option<&'a Visible>,
+}
+
+#[cfg(feature = "gui")]
+impl<'a> View<'a> {
+    pub fn new(
+        layout: Layout,
+        authors: &'a [AuthorProfile],
+        filter: &'a Filter,
+    ) -> Self {
+        Self {
+            layout,
+            authors,
+            filter,
+            visible: None,
+        }
+    }
+}
+
+/// Highest [`Node::rating`].
+pub const MAX_RATING: u8 = 5;
+
+/// Editorial flags for a [`Node`].
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[serde(default)]
+pub struct Flags {
+    /// The node is good as is.
+    pub keep: bool,
+    /// The node should not be used.
+    pub rejected: bool,
+    /// The node is worth keeping but needs editing.
+    pub needs_edit: bool,
+}
+
+impl Flags {
+    /// Flag names and their badges.
+    pub const BADGES: [(&'static str, &'static str); 3] =
+        [("Keep", "✔"), ("Rejected", "✖"), ("Needs edit", "✏")];
+
+    /// Mutable flags in the order of [`Flags::BADGES`].
+    pub fn iter_mut(&mut self) -> [&mut bool; 3] {
+        [&mut self.keep, &mut self.rejected, &mut self.needs_edit]
+    }
+
+    /// Badges for the flags that are set.
+    pub fn badges(&self) -> impl Iterator<Item = &'static str> {
+        [self.keep, self.rejected, self.needs_edit]
+            .into_iter()
+            .zip(Self::BADGES)
+            .filter_map(|(set, (_, badge))| set.then_some(badge))
+    }
+}
+
 /// Node metadata.
 #[derive(Clone, Serialize, Deserialize)]
 #[cfg(feature = "gui")]
@@ -73,6 +251,17 @@ pub struct Meta {
     /// Velocity.
     #[serde(skip)]
     pub vel: egui::Vec2,
+    /// Pinned nodes stay where they were put. The layout doesn't move them,
+    /// but other nodes are still laid out around them.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Selected for bulk operations by Shift or Command clicking the node, or
+    /// lassoing it.
+    #[serde(skip)]
+    pub(crate) selected: bool,
+    /// Index of the child that was most recently on the active path.
+    #[serde(skip)]
+    pub(crate) last_child: Option<usize>,
 }
 
 #[cfg(feature = "gui")]
@@ -105,6 +294,9 @@ impl Default for Meta {
             pos: egui::Pos2::new(0.0, 0.0),
             size: egui::Vec2::new(0.0, 0.0),
             vel: egui::Vec2::new(0.0, 0.0),
+            pinned: false,
+            selected: false,
+            last_child: None,
         }
     }
 }
@@ -125,6 +317,20 @@ pub enum PositionalLayout {
         /// How fast the layout should converge.
         speed: f32,
     },
+    /// Tidy, layered tree layout.
+    Tree {
+        /// Which way the tree grows.
+        direction: Direction,
+        /// Space between siblings (and neighbouring subtrees).
+        sibling_gap: f32,
+        /// Space between generations.
+        layer_gap: f32,
+    },
+    /// Radial layout with the root in the center.
+    Radial {
+        /// Least space between rings.
+        ring_gap: f32,
+    },
 }
 
 #[cfg(feature = "gui")]
@@ -133,6 +339,8 @@ impl PositionalLayout {
     pub const fn as_str(&self) -> &str {
         match self {
             Self::ForceDirected { .. } => "Force Directed",
+            Self::Tree { .. } => "Tree",
+            Self::Radial { .. } => "Radial",
         }
     }
 
@@ -146,6 +354,20 @@ impl PositionalLayout {
         }
     }
 
+    /// Tree layout default.
+    pub const fn tree() -> Self {
+        Self::Tree {
+            direction: Direction::TopDown,
+            sibling_gap: 32.0,
+            layer_gap: 64.0,
+        }
+    }
+
+    /// Radial layout default.
+    pub const fn radial() -> Self {
+        Self::Radial { ring_gap: 64.0 }
+    }
+
     /// UI for the layout.
     pub fn ui(&mut self, ui: &mut egui::Ui) -> egui::Response {
         match self {
@@ -187,262 +409,67 @@ impl PositionalLayout {
                             )
                     }).response
             }
+            Self::Tree {
+                direction,
+                sibling_gap,
+                layer_gap,
+            } => {
+                let mut response = ui
+                    .horizontal(|ui| {
+                        for d in Direction::ALL {
+                            ui.selectable_value(direction, d, d.as_str());
+                        }
+                    })
+                    .response;
+                response |= ui
+                    .add(
+                        egui::Slider::new(sibling_gap, 0.0..=256.0)
+                            .text("siblings"),
+                    )
+                    .on_hover_text_at_pointer("Space between siblings.");
+                response |= ui
+                    .add(
+                        egui::Slider::new(layer_gap, 0.0..=256.0)
+                            .text("layers"),
+                    )
+                    .on_hover_text_at_pointer("Space between generations.");
+                response
+            }
+            Self::Radial { ring_gap } => ui
+                .add(egui::Slider::new(ring_gap, 0.0..=256.0).text("rings"))
+                .on_hover_text_at_pointer("Least space between generations."),
         }
     }
 
-    /// Apply one iteration of force-directed layout to the node. Window
-    /// `bounds` should be supplied to keep the nodes within the window.
-    ///
-    /// If `debug` is supplied, the bounding rectangles of the nodes as well as
-    /// some other debug information will be drawn.
+    /// Lay out the whole tree under `root`. A force-directed layout takes one
+    /// step of its simulation. Other layouts move nodes smoothly toward their
+    /// places.
     ///
     /// Returns true if redraw is needed.
-    pub fn apply(
-        self,
-        node: &mut Node<Meta>,
-        bounds: egui::Rect,
-        debug: Option<&mut egui::Ui>,
-        global_centroid: Pos2,
-        global_cum_mass: f32,
-        mut time_step: f32,
-    ) -> bool {
-        let mut redraw = false;
-
-        match self {
+    pub fn arrange(self, root: &mut Node<Meta>, time_step: f32) -> bool {
+        let targets = match self {
             Self::ForceDirected {
                 repulsion,
                 attraction,
                 gravity,
                 speed,
             } => {
-                // The general idea is for nodes to repel each other with
-                // inverse square force and attract to each other with linear
-                // force where an edge is present. If nodes overlap, the force
-                // is reversed. The nodes also bounce off the boundaries.
-
-                // We avoid quadratic complexity by only calculating the force
-                // between node and siblings and siblings with each other.
-                // This means that forces between cousins are not calculated,
-                // but it's good enough for a tree.
-
-                // Thank you, Bing's Copilot for pointing out that I was missing
-                // the time step here. Also for pointing out that I was using
-                // the distance between child and node to calculate force for
-                // siblings below.
-
-                // There is also a global and local centroid and mass. The nodes
-                // are attracted to a weighted average of these centroids. This
-                // is to keep the tree centered and balanced.
-
-                time_step *= speed;
-
-                let mut stack = vec![(node, None)];
-                while let Some((node, parent_meta)) = stack.pop() {
-                    // Apply damping to the velocity.
-                    node.meta.vel *= 1.0 - DAMPING;
-
-                    // This node's mass and bounding rectangle.
-                    let mass = node.meta.mass();
-                    let rect = node.meta.rect();
-
-                    // In debug mode, draw the bounding rectangle of the node.
-                    if let Some(ref ui) = debug {
-                        egui::Area::new(egui::Id::new(("area", node.meta.id)))
-                            .show(ui.ctx(), |ui| {
-                                ui.painter().rect_filled(
-                                    rect,
-                                    0.0,
-                                    egui::Color32::from_rgba_premultiplied(
-                                        64, 0, 0, 128,
-                                    ),
-                                );
-                            });
-                    }
-
-                    // The local centroid and cumulative mass (just self and
-                    // children)
-                    let mut centroid = node.meta.pos;
-                    // FIXME: when calculating the centroid we should also
-                    // take the mass into account. Currently we're just
-                    // averaging the positions which is wrong. It does converge
-                    // and it's good enough for now, but it's wrong. Thank you
-                    // ChatGPT 4o for pointing this out. A correct example
-                    // is in the `Node::centroid` method.
-                    let mut cum_mass = mass;
-
-                    // Child-to-child interactions. They repel each other. Since
-                    // they do not have edges, they do not attract each other.
-                    for i in 0..node.children.len() {
-                        let a_mass = node.children[i].meta.mass();
-
-                        // Accumulate the local centroid and cumulative mass.
-                        centroid += node.children[i].meta.pos.to_vec2();
-                        cum_mass += a_mass;
-
-                        for j in 0..node.children.len() {
-                            if i == j {
-                                continue;
-                            }
-
-                            let b = &node.children[j];
-                            let b_mass = b.meta.mass();
-
-                            let dist =
-                                node.children[i].meta.pos.distance(b.meta.pos);
-                            let force = repulsion * a_mass * b_mass
-                                / dist.powi(2)
-                                * (node.children[i].meta.pos - b.meta.pos)
-                                    .normalized();
-
-                            // Children always repel each other.
-                            node.children[i].meta.vel += force * time_step;
-                        }
-
-                        // Repel parent node (if any)
-                        if let Some(ref parent) = parent_meta {
-                            let parent: &Meta = parent;
-                            let dist =
-                                node.children[i].meta.pos.distance(parent.pos);
-                            let force = repulsion * a_mass * mass
-                                / dist.powi(2)
-                                * (node.children[i].meta.pos - parent.pos)
-                                    .normalized();
-                            // Repulsion from parent should be stronger. This
-                            // helps make the tree more balanced and tree-like.
-                            node.children[i].meta.vel +=
-                                force * LOCAL_GLOBAL_RATIO * time_step;
-                            cum_mass += parent.mass();
-                            centroid += parent.pos.to_vec2();
-                        }
-                    }
-
-                    // plus 2 for self and the parent node.
-                    centroid = centroid
-                        / (node.children.len() as f32
-                            + (if parent_meta.is_some() { 2.0 } else { 1.0 }));
-
-                    // In debug mode, draw the local centroid.
-                    if gravity > 0.0 {
-                        if let Some(ref ui) = debug {
-                            egui::Area::new(egui::Id::new((
-                                "centroid",
-                                node.meta.id,
-                            )))
-                            .show(
-                                ui.ctx(),
-                                |ui| {
-                                    ui.painter().circle(
-                                        centroid,
-                                        gravity * cum_mass / 10.0,
-                                        egui::Color32::from_rgba_premultiplied(
-                                            128, 0, 0, 128,
-                                        ),
-                                        egui::Stroke::NONE,
-                                    );
-                                },
-                            );
-                        }
-                    }
-
-                    // Our final centroid and cumulative mass is a weighted
-                    // average of the local and global centroids and masses.
-                    // This is hella approximate, but it works.
-                    centroid = (centroid * (LOCAL_GLOBAL_RATIO - 1.0)
-                        + global_centroid.to_vec2())
-                        / LOCAL_GLOBAL_RATIO;
-                    cum_mass = (cum_mass * (LOCAL_GLOBAL_RATIO - 1.0)
-                        + global_cum_mass)
-                        / LOCAL_GLOBAL_RATIO;
-
-                    if gravity > 0.0 && !rect.contains(centroid) {
-                        // In debug builds, draw the final centroid
-                        if let Some(ref ui) = debug {
-                            egui::Area::new(egui::Id::new((
-                                "centroid",
-                                node.meta.id,
-                            )))
-                            .show(
-                                ui.ctx(),
-                                |ui| {
-                                    ui.painter().circle(
-                                        centroid,
-                                        gravity * cum_mass / 10.0,
-                                        egui::Color32::from_rgba_premultiplied(
-                                            128, 128, 0, 128,
-                                        ),
-                                        egui::Stroke::NONE,
-                                    );
-                                },
-                            );
-                        }
-
-                        let dist = node.meta.pos.distance(centroid);
-                        let force = gravity * mass * cum_mass / dist.powi(2)
-                            * (centroid - node.meta.pos).normalized();
-                        node.meta.vel += force * time_step;
-                    }
-
-                    // Bounce off the boundaries. Thanks to Bing's Copilot for
-                    // suggesting this. I used the same idea below for the
-                    // node colissions.
-                    let new_pos = egui::Rect::from_center_size(
-                        node.meta.pos + node.meta.vel,
-                        node.meta.size,
-                    );
-                    if !bounds.contains_rect(new_pos) {
-                        node.meta.vel = -node.meta.vel * BOUNDARY_DAMPING;
-                    }
-
-                    // DAMPING is also used as a cutoff for velocity. If the
-                    // Node isn't moving, we don't need to update the position.
-                    // If no nodes are moving, we don't need to redraw. At that
-                    // point the simulation has converged.
-                    if node.meta.vel.normalized().abs().max_elem()
-                        >= (DAMPING / 10.0)
-                    {
-                        node.meta.vel = node.meta.vel.clamp(
-                            egui::Vec2::splat(-PADDING),
-                            egui::Vec2::splat(PADDING),
-                        );
-                        node.meta.pos += node.meta.vel;
-                        node.meta.pos =
-                            node.meta.pos.clamp(bounds.min, bounds.max);
-
-                        // If the node has moved, we need to redraw.
-                        redraw = true;
-                    }
-
-                    // Child-to-node interactions. They attract each other.
-                    // They do have edges so they also repel each other.
-                    for child in node.children.iter_mut() {
-                        // Attract to node.
-                        let child_mass = child.meta.mass();
-                        let child_rect = child.meta.rect();
-                        let dist = node.meta.pos.distance(child.meta.pos);
-                        let attraction_force = attraction * mass * child_mass
-                            / dist
-                            * (node.meta.pos - child.meta.pos).normalized();
-                        let repulsion_force = repulsion * mass * child_mass
-                            / dist.powi(2)
-                            * (node.meta.pos - child.meta.pos).normalized();
-                        let force = attraction_force - repulsion_force;
-
-                        if !rect.intersects(child_rect) {
-                            child.meta.vel += force * time_step;
-                        } else {
-                            child.meta.vel -= force * time_step;
-                            child.meta.vel *= BOUNDARY_DAMPING;
-                        }
-
-                        // Recurse into the child, providing this node's
-                        // metadata as the parent metadata.
-                        stack.push((child, Some(node.meta.clone())));
-                    }
-                }
+                return crate::layout::force_directed(
+                    root,
+                    repulsion,
+                    attraction,
+                    gravity,
+                    time_step * speed,
+                );
             }
-        }
-
-        redraw
+            Self::Tree {
+                direction,
+                sibling_gap,
+                layer_gap,
+            } => crate::layout::tidy(root, direction, sibling_gap, layer_gap),
+            Self::Radial { ring_gap } => crate::layout::radial(root, ring_gap),
+        };
+        crate::layout::animate(root, &targets, time_step)
     }
 }
 
@@ -459,9 +486,7 @@ impl PartialEq for PositionalLayout {
     /// a combo box below and we don't compare it anywhere else. This is "bad",
     /// but it's fine for now.
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Self::ForceDirected { .. }, Self::ForceDirected { .. }) => true,
-        }
+        std::mem::discriminant(self) == std::mem::discriminant(other)
     }
 }
 
@@ -473,6 +498,9 @@ pub struct Layout {
     auto_collapse: bool,
     /// Positional layout.
     positional: Option<PositionalLayout>,
+    /// Don't move nodes, keeping the positional layout's settings.
+    #[serde(default)]
+    frozen: bool,
 }
 
 #[cfg(feature = "gui")]
@@ -481,6 +509,7 @@ impl Default for Layout {
         Self {
             auto_collapse: false,
             positional: None,
+            frozen: false,
         }
     }
 }
@@ -492,11 +521,13 @@ impl Layout {
         ui.horizontal(|ui| {
             ui.toggle_value(&mut self.auto_collapse, "auto-collapse")
                 .on_hover_text_at_pointer(
-                    "Collapse all nodes except selected. Note that for the moment this only works for existing nodes in the tree view.",
+                    "Collapse all nodes except those on the active path.",
                 );
             let mut layout_positions = self.positional.is_some();
             ui.toggle_value(&mut layout_positions, "auto-layout")
-                .on_hover_text_at_pointer("(experimental) Organize nodes automatically.");
+                .on_hover_text_at_pointer(
+                    "(experimental) Organize nodes automatically.",
+                );
             if layout_positions {
                 let positional =
                     self.positional.get_or_insert_with(Default::default);
@@ -508,8 +539,22 @@ impl Layout {
                             PositionalLayout::force_directed(),
                             "Force Directed",
                         );
+                        ui.selectable_value(
+                            positional,
+                            PositionalLayout::tree(),
+                            "Tree",
+                        );
+                        ui.selectable_value(
+                            positional,
+                            PositionalLayout::radial(),
+                            "Radial",
+                        );
                     });
                 positional.ui(ui);
+                ui.toggle_value(&mut self.frozen, "freeze")
+                    .on_hover_text_at_pointer(
+                        "Stop the layout so that nodes stay where they are.",
+                    );
             } else {
                 self.positional = None;
             }
@@ -517,6 +562,25 @@ impl Layout {
     }
 }
 
+/// Drag-and-drop payload for a node being dragged on the canvas.
+#[cfg(feature = "gui")]
+struct Dragged {
+    /// Id of the dragged node.
+    id: u128,
+    /// Ids of the node and its descendants, which it can't be dropped on.
+    subtree: std::collections::HashSet<u128>,
+}
+
+#[cfg(feature = "gui")]
+impl Dragged {
+    fn new(node: &Node<Meta>) -> Self {
+        Self {
+            id: node.meta.id,
+            subtree: node.iter_depth_first().map(|n| n.meta.id).collect(),
+        }
+    }
+}
+
 /// An action is needed for a node. All actions imply selection of either the
 /// current node or a child node.
 #[cfg(feature = "gui")]
@@ -531,6 +595,9 @@ pub struct Action {
     /// If the node (or tree) has been modified. This is an optimization to
     /// avoid unnecessary rendering, allocation, and node traversal.
     pub modified: bool,
+    /// The node with this id was dropped on this node and should become its
+    /// child.
+    pub reparent: Option<u128>,
 }
 
 #[cfg(feature = "gui")]
@@ -564,7 +631,7 @@ pub struct Meta;
 
 impl<T> Node<T> {
     /// Create a new node with author id.
-    pub fn with_author(author_id: u8) -> Self
+    pub fn with_author(author_id: AuthorIndex) -> Self
     where
         T: Default,
     {
@@ -574,11 +641,41 @@ impl<T> Node<T> {
         }
     }
 
-    /// Set author for the node and all children.
-    pub fn set_author(&mut self, author_id: u8) {
+    /// Set author for the node only. Use [`Node::map_authors`] to set the
+    /// author of the node and all children.
+    pub fn set_author(&mut self, author_id: AuthorIndex) {
         self.author_id = author_id;
-        for child in self.children.iter_mut() {
-            child.set_author(author_id);
+    }
+
+    /// Replace the author of the node and all children using a function of
+    /// the current author id. This is useful for moving nodes between stories
+    /// where author ids differ.
+    pub fn map_authors<F>(&mut self, mut f: F)
+    where
+        F: FnMut(AuthorIndex) -> AuthorIndex,
+    {
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            node.author_id = f(node.author_id);
+            stack.extend(node.children.iter_mut());
+        }
+    }
+
+    /// Clone just this node, without any children.
+    pub fn clone_childless(&self) -> Self
+    where
+        T: Clone,
+    {
+        Self {
+            author_id: self.author_id,
+            text: self.text.clone(),
+            pieces: self.pieces.clone(),
+            rating: self.rating,
+            flags: self.flags,
+            tags: self.tags.clone(),
+            notes: self.notes.clone(),
+            children: Vec::new(),
+            meta: self.meta.clone(),
         }
     }
 
@@ -680,6 +777,25 @@ impl<T> Node<T> {
         })
     }
 
+    /// Iterate all nodes in the tree in depth-first order along with their
+    /// paths from self. The path of self is empty.
+    pub fn iter_depth_first_paths<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (Vec<usize>, &'a Node<T>)> + 'a {
+        let mut stack = vec![(Vec::new(), self)];
+        std::iter::from_fn(move || {
+            let (path, node) = stack.pop()?;
+            stack.extend(node.children.iter().enumerate().rev().map(
+                |(i, child)| {
+                    let mut child_path = path.clone();
+                    child_path.push(i);
+                    (child_path, child)
+                },
+            ));
+            Some((path, node))
+        })
+    }
+
     /// Iterate Pieces of the node as strings.
     pub fn iter_pieces<'a>(&'a self) -> impl Iterator<Item = &'a str> + 'a {
         self.pieces
@@ -710,6 +826,103 @@ impl<T> Node<T> {
             .skip(1)
     }
 
+    /// Add a `tag` if it's not empty and the node doesn't already have it.
+    /// Surrounding whitespace is trimmed. Returns true if the tag was added.
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        let tag = tag.trim();
+        if tag.is_empty() || self.tags.iter().any(|t| t == tag) {
+            false
+        } else {
+            self.tags.push(tag.to_string());
+            true
+        }
+    }
+
+    /// Index of the best child. Rejected children rank last, then children
+    /// are ranked by rating, with kept children preferred among equals. Ties
+    /// go to the first child.
+    pub fn best_child(&self) -> Option<usize> {
+        self.children
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, child)| {
+                (!child.flags.rejected, child.rating, child.flags.keep)
+            })
+            .map(|(i, _)| i)
+    }
+
+    /// Remove descendants for which `keep` returns false, along with their
+    /// children. Returns the number of nodes removed.
+    pub fn prune<F>(&mut self, keep: &F) -> usize
+    where
+        F: Fn(&Node<T>) -> bool,
+    {
+        let mut removed = 0;
+        self.children.retain(|child| {
+            let keep = keep(child);
+            if !keep {
+                removed += child.count();
+            }
+            keep
+        });
+        for child in self.children.iter_mut() {
+            removed += child.prune(keep);
+        }
+        removed
+    }
+
+    /// Replace byte `ranges` of the text and adjust the pieces. Ranges must be
+    /// sorted and must not overlap. A piece ending inside a replaced range is
+    /// extended to the end of the replacement, and pieces left empty are
+    /// removed.
+    ///
+    /// # Panics
+    /// - If a range is out of bounds or not on a char boundary.
+    pub fn replace_ranges<'a, I>(&mut self, ranges: I)
+    where
+        I: IntoIterator<Item = (std::ops::Range<usize>, &'a str)>,
+    {
+        let ranges: Vec<_> = ranges.into_iter().collect();
+        if ranges.is_empty() {
+            return;
+        }
+
+        // Map an old end offset to a new one.
+        let map = |end: usize| -> usize {
+            let mut delta: isize = 0;
+            for (range, with) in ranges.iter() {
+                if range.end <= end {
+                    delta += with.len() as isize - range.len() as isize;
+                } else if range.start < end {
+                    return (range.start as isize + delta) as usize
+                        + with.len();
+                } else {
+                    break;
+                }
+            }
+            (end as isize + delta) as usize
+        };
+
+        let mut text = String::with_capacity(self.text.len());
+        let mut last = 0;
+        for (range, with) in ranges.iter() {
+            text.push_str(&self.text[last..range.start]);
+            text.push_str(with);
+            last = range.end;
+        }
+        text.push_str(&self.text[last..]);
+
+        let mut start = 0;
+        self.pieces.retain_mut(|piece| {
+            piece.end = map(piece.end);
+            let keep = piece.end > start;
+            start = start.max(piece.end);
+            keep
+        });
+        self.text = text;
+    }
+
     /// Trim whitespace from the end of the text and adjust the pieces.
     pub fn trim_end_whitespace(&mut self) {
         let len = self.text.trim_end().len();
@@ -749,45 +962,39 @@ impl Node<Meta> {
         ui: &mut egui::Ui,
         active_path: Option<&[usize]>,
         lock_topology: bool,
-        layout: Layout,
         time_step: f32,
+        view: View<'_>,
     ) -> Option<PathAction> {
+        let View {
+            layout,
+            authors,
+            filter,
+            ..
+        } = view;
         let active_path = active_path.unwrap_or(&[]);
         let mut ret = None; // the default, meaning no action is needed.
 
-        // These are used for layout. We only calculate them if we need to.
-        let mut global_centroid = Pos2::ZERO;
-        let mut global_cum_mass = 0.0;
-        #[allow(unused_variables)] // because pos is only used in debug builds,
-        // but we still need the if block.
-        if let Some(pos) = layout.positional {
-            // Calculate the global centroid and mass of the tree.
-            let (_, global_centroid_, global_cum_mass_) = self.centroid();
-            global_centroid = global_centroid_;
-            global_cum_mass = global_cum_mass_;
-
-            // Debug build drawing.
+        if let Some(pos) = layout.positional.filter(|_| !layout.frozen) {
+            // Debug build drawing of the global centroid and mass.
             #[cfg(debug_assertions)]
-            match pos {
-                PositionalLayout::ForceDirected { gravity, .. } => {
-                    egui::Area::new(egui::Id::new("global_centroid")).show(
-                        ui.ctx(),
-                        |ui| {
-                            ui.painter().circle(
-                                global_centroid,
-                                gravity * global_cum_mass / 10.0,
-                                egui::Color32::from_rgba_premultiplied(
-                                    0, 128, 0, 128,
-                                ),
-                                egui::Stroke::NONE,
-                            );
-                        },
-                    );
-                }
+            if let PositionalLayout::ForceDirected { gravity, .. } = pos {
+                let (_, centroid, mass) = self.centroid();
+                ui.painter().circle(
+                    centroid,
+                    gravity * mass / 10.0,
+                    egui::Color32::from_rgba_premultiplied(0, 128, 0, 128),
+                    egui::Stroke::NONE,
+                );
+            }
+
+            if pos.arrange(self, time_step) {
+                // Positions have changed, request a repaint.
+                ui.ctx().request_repaint();
             }
         }
 
         // The current path in the tree.
+        let matcher = filter.matcher();
         let mut current_path = Vec::new();
 
         // The stack data is:
@@ -818,9 +1025,8 @@ impl Node<Meta> {
                 highlight_node,
                 lock_topology,
                 layout,
-                global_centroid,
-                global_cum_mass,
-                time_step,
+                authors,
+                matcher.matches(node),
             ) {
                 if action.delete {
                     // How to delete a node? We're taking a reference to the
@@ -948,6 +1154,19 @@ impl Node<Meta> {
         resp
     }
 
+    /// Give the node and all children new unique ids. This should be done
+    /// when copying nodes so the copies aren't confused with the originals.
+    /// The copies aren't selected.
+    #[cfg(feature = "gui")]
+    pub fn renew_ids(&mut self) {
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            node.meta.id = Meta::default().id;
+            node.meta.selected = false;
+            stack.extend(node.children.iter_mut());
+        }
+    }
+
     /// Create a new child, below the parent's position.
     ///
     /// Returns the index of the new child.
@@ -998,7 +1217,13 @@ impl Node<Meta> {
         resp
     }
 
-    /// Draw just the node. Returns true if the node should be active.
+    /// Draw just the node, on a [`Canvas`], at its position. Nodes outside
+    /// the visible part of the canvas are laid out but not drawn. When zoomed
+    /// out, or collapsed by the `layout`, a preview is drawn instead of the
+    /// editor. Shift or Command clicking the node toggles its selection.
+    /// Returns an action if the node should be active.
+    ///
+    /// [`Canvas`]: crate::canvas::Canvas
     #[cfg(feature = "gui")]
     pub fn draw_one_node(
         &mut self,
@@ -1006,130 +1231,173 @@ impl Node<Meta> {
         highlighted: bool,
         lock_topology: bool,
         layout: Layout,
-        global_centroid: Pos2,
-        global_cum_mass: f32,
-        time_step: f32,
+        authors: &[AuthorProfile],
+        matches_filter: bool,
     ) -> Option<Action> {
-        // because this is only used in debug builds.
-        #[allow(unused_assignments)]
-        let mut repaint = false;
-        let screen_rect = ui.ctx().screen_rect();
-        if let Some(positional) = layout.positional {
-            repaint = positional.apply(
-                self,
-                screen_rect,
-                if cfg!(debug_assertions) {
-                    Some(ui)
-                } else {
-                    None
-                },
-                global_centroid,
-                global_cum_mass,
-                time_step,
-            );
-            if repaint {
-                // Positions have changed, request a repaint.
-                ui.ctx().request_repaint();
-            }
+        // Whether the layout is moving the node. Only used in debug builds.
+        let moving =
+            layout.positional.is_some() && self.meta.vel != egui::Vec2::ZERO;
+
+        let rect = egui::Rect::from_min_size(self.meta.pos, self.meta.size);
+        if !ui.clip_rect().intersects(rect.expand(PADDING)) {
+            return None;
         }
 
-        #[cfg(not(debug_assertions))]
-        let frame = egui::Frame::window(&ui.ctx().style())
-            .fill(egui::Color32::from_gray(64));
-
-        #[cfg(debug_assertions)]
-        let frame = egui::Frame::window(&ui.ctx().style())
-            .stroke(if repaint {
-                egui::Stroke::new(
-                    self.meta.vel.abs().max_elem().min(PADDING).max(1.0),
-                    egui::Color32::RED,
-                )
-            } else {
-                egui::Stroke::NONE
-            })
-            .fill(egui::Color32::from_gray(64));
-
-        let title = self
-            .text
-            .chars()
-            .take(16)
-            .chain(std::iter::once('…'))
-            .collect::<String>();
-
-        let window = egui::Window::new(&title)
-            .id(egui::Id::new(self.meta.id))
-            .collapsible(true)
-            .title_bar(true)
-            .default_open(!layout.auto_collapse || highlighted)
-            .auto_sized()
-            .default_pos(self.meta.pos)
-            .current_pos(self.meta.pos)
-            .constrain_to(ui.ctx().screen_rect())
-            .frame(frame);
-
-        let mut response = window.show(ui.ctx(), |ui| {
-            if highlighted {
-                ui.set_opacity(1.5);
-            } else {
-                ui.set_opacity(0.5);
-            }
+        let detailed = crate::canvas::zoom(ui) >= crate::canvas::DETAIL_ZOOM
+            && (!layout.auto_collapse || highlighted);
+        let width = if detailed { NODE_WIDTH } else { PREVIEW_WIDTH };
+
+        // The background is added first so the node's widgets are on top of
+        // it. Clicking it selects the node and dragging it moves the node.
+        let background = ui.interact(
+            rect,
+            egui::Id::new(("node", self.meta.id)),
+            egui::Sense::click_and_drag(),
+        );
+        // Dropping a node on another makes it a child of the other. A node
+        // can't be dropped on itself or its descendants.
+        if background.drag_started() && !lock_topology {
+            background.dnd_set_drag_payload(Dragged::new(self));
+        }
+        let droppable = background
+            .dnd_hover_payload::<Dragged>()
+            .filter(|dragged| dragged.id != self.meta.id)
+            .map(|dragged| !dragged.subtree.contains(&self.meta.id));
+        let mut reparent = None;
+        if droppable == Some(true) {
+            reparent = background
+                .dnd_release_payload::<Dragged>()
+                .map(|dragged| dragged.id);
+        } else if droppable == Some(false) {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::NotAllowed);
+        }
 
-            let mut action = None;
-            if !lock_topology {
-                self.draw_buttons(ui, &mut action);
-            }
+        let author = authors.get(self.author_id as usize);
+        let stroke = if cfg!(debug_assertions) && moving {
+            egui::Stroke::new(
+                self.meta.vel.abs().max_elem().min(PADDING).max(1.0),
+                egui::Color32::RED,
+            )
+        } else if droppable == Some(true) {
+            egui::Stroke::new(3.0, ui.visuals().selection.stroke.color)
+        } else if self.meta.selected {
+            egui::Stroke::new(3.0, ui.visuals().selection.bg_fill)
+        } else if highlighted {
+            ui.visuals().selection.stroke
+        } else {
+            ui.visuals().window_stroke
+        };
+        // Shadows are expensive with many nodes.
+        let frame = egui::Frame::window(ui.style())
+            .shadow(egui::epaint::Shadow::NONE)
+            .fill(tint(author.and_then(AuthorProfile::color32)))
+            .stroke(stroke);
+
+        let title = self.title(author);
+        let max_rect = egui::Rect::from_min_size(
+            self.meta.pos,
+            egui::vec2(width, f32::INFINITY),
+        );
+        let response = ui.allocate_ui_at_rect(max_rect, |ui| {
+            frame
+                .show(ui, |ui| {
+                    ui.set_width(width - frame.total_margin().sum().x);
+                    if !matches_filter {
+                        ui.set_opacity(0.15);
+                    } else if !highlighted {
+                        ui.set_opacity(0.5);
+                    }
 
-            // We can still allow editing the text during generation since
-            // the pieces are still appended to the end. There is no
-            // ownership issue because of the immediate mode GUI and there
-            // are no topology changes so the new tokens are appended at the
-            // correct path.
-            self.draw_text_edit(ui, &mut action);
+                    let mut action = None;
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(title).strong());
+                        if self.meta.pinned
+                            && ui
+                                .small_button("📌")
+                                .on_hover_text_at_pointer(
+                                    "Pinned where it was dragged. Click to let the layout move it.",
+                                )
+                                .clicked()
+                        {
+                            self.meta.pinned = false;
+                        }
+                    });
+                    if !detailed {
+                        let preview: String =
+                            self.text.chars().take(PREVIEW_CHARS).collect();
+                        ui.add(egui::Label::new(preview).truncate(false));
+                        return action;
+                    }
+                    if !lock_topology {
+                        self.draw_buttons(ui, &mut action);
+                    }
+                    self.draw_rating(ui);
+                    self.draw_annotations(ui);
+
+                    // We can still allow editing the text during generation
+                    // since the pieces are still appended to the end. There is
+                    // no ownership issue because of the immediate mode GUI and
+                    // there are no topology changes so the new tokens are
+                    // appended at the correct path.
+                    self.draw_text_edit(ui, &mut action);
 
-            action
+                    action
+                })
+                .inner
         });
 
-        if let Some(response) = &mut response {
-            if let Some(inner) = response.inner.as_mut() {
-                // If the window was clicked, we need to select the node.
-                if inner.is_none() && response.response.clicked() {
-                    // If the window was clicked, we need to select the node.
-                    inner.replace(Action::default());
-                }
-            }
+        // The size is known only once the node has been drawn.
+        self.meta.size = response.response.rect.size();
+        if background.dragged() {
+            // Dragged nodes stay where they're dropped.
+            self.meta.pos += background.drag_delta();
+            self.meta.vel = egui::Vec2::ZERO;
+            self.meta.pinned = true;
         }
 
-        // If the window has been interacted with, we need to store the new size
-        // and position. We also need to forward any inner activation response
-        // from the closure above to the caller.
-        let ret = if let Some(response) = response {
-            // Response from the *window*.
-            let win = response.response;
-
-            // We set the new size unconditionally but only the position if the
-            // window is being dragged. If we set the position unconditionally,
-            // egui will round the positions which breaks the force-directed
-            // layout. Nodes don't move unless the force is very strong.
-            self.meta.size = win.rect.size();
-            if win.is_pointer_button_down_on() {
-                self.meta.pos = win.rect.min;
-            }
-
-            // Unwrap inner response from the closure and send it to the caller
-            // letting the caller know if any action is needed.
-            response.inner.unwrap_or(None)
-        } else {
-            None
-        };
+        let toggle = background.clicked()
+            && ui.input(|input| {
+                input.modifiers.shift || input.modifiers.command
+            });
+        if toggle {
+            self.meta.selected = !self.meta.selected;
+        }
 
-        // We need to clamp the rect to the screen. This is because the user
-        // can drag the window + padding outside the screen.
-        let rect = constrain(self.meta.rect(), screen_rect).shrink(PADDING);
+        match response.inner {
+            _ if reparent.is_some() => Some(Action {
+                reparent,
+                ..Action::default()
+            }),
+            None if background.clicked() && !toggle => Some(Action::default()),
+            action => action,
+        }
+    }
 
-        self.meta.pos = rect.min;
-        self.meta.size = rect.size();
+    /// Unpin the node and all its descendants. Returns the number unpinned.
+    #[cfg(feature = "gui")]
+    pub fn unpin_all(&mut self) -> usize {
+        let mut count = 0;
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            count += std::mem::take(&mut node.meta.pinned) as usize;
+            stack.extend(node.children.iter_mut());
+        }
+        count
+    }
 
-        ret
+    /// Bounding rectangle of the node and all its descendants.
+    #[cfg(feature = "gui")]
+    pub fn bounds(&self) -> egui::Rect {
+        let mut bounds = egui::Rect::NOTHING;
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            bounds = bounds.union(egui::Rect::from_min_size(
+                node.meta.pos,
+                node.meta.size,
+            ));
+            stack.extend(node.children.iter());
+        }
+        bounds
     }
 
     /// Draw the tree.
@@ -1139,9 +1407,9 @@ impl Node<Meta> {
         ui: &mut egui::Ui,
         selected_path: Option<&[usize]>,
         lock_topology: bool,
-        layout: Layout,
         mode: crate::story::DrawMode,
         time_step: f32,
+        view: View<'_>,
     ) -> Option<PathAction> {
         use crate::story::DrawMode;
 
@@ -1150,11 +1418,18 @@ impl Node<Meta> {
                 ui,
                 selected_path,
                 lock_topology,
-                layout,
                 time_step,
+                view,
             ),
             DrawMode::Tree => {
-                egui::ScrollArea::vertical()
+                // Nodes shown are found once rather than for every node.
+                let visible = (!view.filter.is_empty())
+                    .then(|| view.filter.matcher().visible(self));
+                let view = View {
+                    visible: visible.as_ref(),
+                    ..view
+                };
+                let action = egui::ScrollArea::vertical()
                     .show(ui, |ui| {
                         self.draw_tree(
                             ui,
@@ -1163,15 +1438,138 @@ impl Node<Meta> {
                             0,    // depth
                             true, // selected
                             lock_topology,
-                            layout,
+                            view,
                         )
                     })
-                    .inner
+                    .inner;
+                // The tree has been collapsed, if that was requested.
+                ui.ctx()
+                    .data_mut(|data| data.remove::<bool>(collapse_tree_id()));
+                action
             }
         }
     }
 
-    /// Calculate (node_count, centroid, cumulative_mass) of the tree.
+    /// Short title for the node. If the `author` is known, their initials are
+    /// prepended.
+    #[cfg(feature = "gui")]
+    fn title(&self, author: Option<&AuthorProfile>) -> String {
+        let text = self.text.chars().take(16).chain(std::iter::once('…'));
+        let mut title: String = match author {
+            Some(author) => author
+                .initials
+                .chars()
+                .chain(std::iter::once(' '))
+                .chain(text)
+                .collect(),
+            None => text.collect(),
+        };
+        if let Some(rating) = self.rating {
+            title.push_str(&format!(" ★{}", rating));
+        }
+        for badge in self.flags.badges() {
+            title.push(' ');
+            title.push_str(badge);
+        }
+        title
+    }
+
+    /// Draw rating stars and flag toggles. Returns true if either changed.
+    #[cfg(feature = "gui")]
+    pub fn draw_rating(&mut self, ui: &mut egui::Ui) -> bool {
+        let (rating, flags) = (self.rating, self.flags);
+        ui.horizontal(|ui| {
+            for star in 1..=MAX_RATING {
+                let filled = self.rating.is_some_and(|r| r >= star);
+                if ui
+                    .selectable_label(false, if filled { "★" } else { "☆" })
+                    .on_hover_text_at_pointer(if self.rating == Some(star) {
+                        "Clear rating.".to_string()
+                    } else {
+                        format!("Rate {} of {}.", star, MAX_RATING)
+                    })
+                    .clicked()
+                {
+                    self.rating = if self.rating == Some(star) {
+                        None
+                    } else {
+                        Some(star)
+                    };
+                }
+            }
+            ui.separator();
+            for (flag, (name, badge)) in
+                self.flags.iter_mut().into_iter().zip(Flags::BADGES)
+            {
+                ui.toggle_value(flag, badge).on_hover_text_at_pointer(name);
+            }
+        });
+        rating != self.rating || flags != self.flags
+    }
+
+    /// Draw tag and note editors. Returns true if either changed.
+    #[cfg(feature = "gui")]
+    pub fn draw_annotations(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        let header = if self.tags.is_empty() && self.notes.is_empty() {
+            "Tags & notes".to_string()
+        } else {
+            format!("Tags & notes ({})", self.tags.join(", "))
+        };
+        egui::CollapsingHeader::new(header)
+            .id_source(egui::Id::new(("annotations", self.meta.id)))
+            .show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    let mut remove = None;
+                    for (i, tag) in self.tags.iter().enumerate() {
+                        if ui
+                            .small_button(format!("{} ×", tag))
+                            .on_hover_text_at_pointer("Remove tag.")
+                            .clicked()
+                        {
+                            remove = Some(i);
+                        }
+                    }
+                    if let Some(i) = remove {
+                        self.tags.remove(i);
+                        changed = true;
+                    }
+                });
+
+                // The new tag buffer has to outlive the frame, so it lives in
+                // egui's memory.
+                let id = egui::Id::new(("tag_buf", self.meta.id));
+                let mut buf = ui
+                    .data_mut(|d| d.get_temp::<String>(id))
+                    .unwrap_or_default();
+                ui.horizontal(|ui| {
+                    let edit = ui.add(
+                        egui::TextEdit::singleline(&mut buf)
+                            .hint_text("New tag")
+                            .desired_width(96.0),
+                    );
+                    let submit = edit.lost_focus()
+                        && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if (ui.button("Add").clicked() || submit)
+                        && self.add_tag(&buf)
+                    {
+                        buf.clear();
+                        changed = true;
+                    }
+                });
+                ui.data_mut(|d| d.insert_temp(id, buf));
+
+                changed |= ui
+                    .add(
+                        egui::TextEdit::multiline(&mut self.notes)
+                            .hint_text("Notes (not part of the story)")
+                            .desired_rows(2),
+                    )
+                    .changed();
+            });
+        changed
+    }
+
     /// Calculate (node_count, centroid, cumulative_mass) of the tree.
     pub fn centroid(&self) -> (usize, egui::Pos2, f32) {
         // Thank you ChatGPT 4o for pointing out that I was missing the mass
@@ -1209,6 +1607,11 @@ impl Node<Meta> {
     ///   selected, it will be opened, if not, it will be closed.
     /// - `lock_topology`: Whether the topology is locked. Disables buttons
     ///   that change topology. Editing text is still allowed.
+    /// - `view`: The layout, authors, and the nodes shown and highlighted if
+    ///   filtering.
+    ///
+    /// Shift or Command clicking a node's header toggles its selection for
+    /// bulk operations. Selected nodes' titles are highlighted.
     #[cfg(feature = "gui")]
     fn draw_tree(
         &mut self,
@@ -1218,19 +1621,40 @@ impl Node<Meta> {
         depth: usize,
         selected: bool,
         lock_topology: bool,
-        layout: Layout,
+        view: View<'_>,
     ) -> Option<PathAction> {
-        let title = self
-            .text
-            .chars()
-            .take(16)
-            .chain(std::iter::once('…'))
-            .collect::<String>();
+        let View {
+            layout,
+            authors,
+            visible,
+            ..
+        } = view;
+        // Nodes are shown if they, or any of their descendants, match.
+        if visible.is_some_and(|v| !v.shown.contains(&self.meta.id)) {
+            return None;
+        }
+        let matched =
+            visible.map_or(true, |v| v.matched.contains(&self.meta.id));
+
+        let mut title = egui::RichText::new(
+            self.title(authors.get(self.author_id as usize)),
+        );
+        if self.meta.selected {
+            title = title.background_color(ui.visuals().selection.bg_fill);
+        }
 
+        // Matches are highlighted when filtering.
+        if visible.is_some() && matched {
+            title = title.strong().color(ui.visuals().warn_fg_color);
+        }
+
+        let collapse = ui.ctx().data(|data| {
+            data.get_temp::<bool>(collapse_tree_id()).unwrap_or(false)
+        });
         let open = if selected {
             Some(true)
         } else {
-            if layout.auto_collapse {
+            if layout.auto_collapse || collapse {
                 Some(false)
             } else {
                 None
@@ -1242,7 +1666,7 @@ impl Node<Meta> {
         // very unlikely that the depth of the tree will be so large that it
         // will cause a stack overflow. It's also prettier and easier to
         // understand.
-        egui::CollapsingHeader::new(title)
+        let response = egui::CollapsingHeader::new(title)
             .default_open(open.unwrap_or(false))
             .open(open)
             .id_source(egui::Id::new(("tree", self.meta.id)))
@@ -1250,7 +1674,17 @@ impl Node<Meta> {
                 let mut action: Option<Action> = None;
                 let mut path_action = None;
 
-                if selected {
+                // Scroll to the head when it changes.
+                if selected
+                    && depth == selected_path.map_or(0, <[usize]>::len)
+                    && head_changed(ui, "tree", self.meta.id)
+                {
+                    ui.scroll_to_cursor(Some(egui::Align::Center));
+                }
+
+                if !matched {
+                    ui.set_opacity(0.3);
+                } else if selected {
                     ui.set_opacity(1.0);
                 } else {
                     ui.set_opacity(0.5);
@@ -1260,6 +1694,8 @@ impl Node<Meta> {
                 if !lock_topology {
                     self.draw_buttons(ui, &mut action);
                 }
+                self.draw_rating(ui);
+                self.draw_annotations(ui);
 
                 // Draw text edit
                 self.draw_text_edit(ui, &mut action);
@@ -1278,7 +1714,7 @@ impl Node<Meta> {
                         depth + 1,
                         selected,
                         lock_topology,
-                        layout,
+                        view,
                     ) {
                         path_action = Some(a);
                     }
@@ -1292,8 +1728,55 @@ impl Node<Meta> {
                 } else {
                     path_action
                 }
-            })
-            .body_returned?
+            });
+        if response.header_response.clicked()
+            && ui
+                .input(|input| input.modifiers.shift || input.modifiers.command)
+        {
+            self.meta.selected = !self.meta.selected;
+        }
+
+        response.body_returned?
+    }
+}
+
+/// Returns true if the head of a `view` is not the node with `id` it was last
+/// time this was called.
+#[cfg(feature = "gui")]
+fn head_changed(ui: &egui::Ui, view: &str, id: u128) -> bool {
+    let key = egui::Id::new(("head", view));
+    ui.ctx().data_mut(|data| {
+        let changed = data.get_temp::<u128>(key) != Some(id);
+        data.insert_temp(key, id);
+        changed
+    })
+}
+
+/// Id of the flag set by [`collapse_tree`].
+#[cfg(feature = "gui")]
+fn collapse_tree_id() -> egui::Id {
+    egui::Id::new("collapse tree")
+}
+
+/// Collapse every node in the tree view, except those on the active path, the
+/// next time it's drawn. Nodes can be expanded again afterwards.
+#[cfg(feature = "gui")]
+pub fn collapse_tree(ctx: &egui::Context) {
+    ctx.data_mut(|data| data.insert_temp(collapse_tree_id(), true));
+}
+
+/// Node background, tinted by an author's `color` if any.
+#[cfg(feature = "gui")]
+fn tint(color: Option<egui::Color32>) -> egui::Color32 {
+    const GRAY: u16 = 64;
+    match color {
+        // One part color to three parts gray so text stays readable.
+        Some(c) => egui::Color32::from_rgb(
+            ((GRAY * 3 + c.r() as u16) / 4) as u8,
+            ((GRAY * 3 + c.g() as u16) / 4) as u8,
+            ((GRAY * 3 + c.b() as u16) / 4) as u8,
+        ),
+        None => egui::Color32::from_gray(GRAY as u8),
     }
 }
 
@@ -1315,6 +1798,47 @@ fn draw_line(ui: &mut egui::Ui, src: Meta, dst: Meta, highlighted: bool) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_filter_criteria() {
+        let mut node = Node::<Meta>::with_author(1);
+        node.text = "The Quick brown fox".to_string();
+        node.rating = Some(3);
+        node.tags.push("draft".to_string());
+        assert!(Filter::default().is_empty());
+        assert!(Filter::default().matches(&node));
+
+        let filter = Filter {
+            text: " quick ".to_string(),
+            author: Some(1),
+            tag: Some("draft".to_string()),
+            min_rating: Some(3),
+        };
+        assert!(!filter.is_empty());
+        assert!(filter.matches(&node));
+        for filter in [
+            Filter {
+                text: "slow".to_string(),
+                ..filter.clone()
+            },
+            Filter {
+                author: Some(0),
+                ..filter.clone()
+            },
+            Filter {
+                min_rating: Some(4),
+                ..filter.clone()
+            },
+        ] {
+            assert!(!filter.matches(&node));
+        }
+
+        // Ancestors of matches are kept.
+        let mut root = Node::<Meta>::default();
+        root.add_child(node);
+        assert!(!filter.matches(&root));
+        assert!(filter.matches_subtree(&root));
+    }
+
     #[test]
     fn test_iter_path_nodes() {
         let mut root = Node::<Meta>::default();
@@ -1399,4 +1923,110 @@ mod tests {
         let letters = nodes.iter().flat_map(|node| node.iter_pieces());
         assert_eq!(letters.collect::<String>(), "abcdghef");
     }
+
+    #[test]
+    fn test_iter_depth_first_paths() {
+        let mut root = Node::<Meta>::default();
+        root.add_child(Node::default());
+        root.add_child(Node::default());
+        root.children[0].add_child(Node::default());
+
+        let paths: Vec<_> = root
+            .iter_depth_first_paths()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, vec![vec![], vec![0], vec![0, 0], vec![1]]);
+        for path in paths {
+            assert!(root.is_valid_path(&path));
+        }
+    }
+
+    #[test]
+    fn test_replace_ranges() {
+        let mut node = Node::<Meta>::default();
+        node.extend_strings(vec!["Bob", " met", " Bob."]);
+
+        node.replace_ranges([(0..3, "Robert"), (8..11, "Rob")]);
+        assert_eq!(node.text, "Robert met Rob.");
+        let pieces: Vec<_> = node.iter_pieces().collect();
+        assert_eq!(pieces, vec!["Robert", " met", " Rob."]);
+
+        // A replacement spanning pieces merges them.
+        node.replace_ranges([(3..10, "")]);
+        assert_eq!(node.text, "Rob Rob.");
+        let pieces: Vec<_> = node.iter_pieces().collect();
+        assert_eq!(pieces, vec!["Rob", " Rob."]);
+
+        // Replacing a whole piece with nothing removes it.
+        node.replace_ranges([(0..3, "")]);
+        let pieces: Vec<_> = node.iter_pieces().collect();
+        assert_eq!(pieces, vec![" Rob."]);
+    }
+
+    #[test]
+    fn test_best_child() {
+        let mut root = Node::<Meta>::default();
+        assert_eq!(root.best_child(), None);
+        for _ in 0..4 {
+            root.add_child(Node::default());
+        }
+        assert_eq!(root.best_child(), Some(0));
+        root.children[1].rating = Some(3);
+        root.children[2].rating = Some(3);
+        assert_eq!(root.best_child(), Some(1));
+        root.children[2].flags.keep = true;
+        assert_eq!(root.best_child(), Some(2));
+        root.children[3].rating = Some(5);
+        root.children[3].flags.rejected = true;
+        assert_eq!(root.best_child(), Some(2));
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut root = Node::<Meta>::default();
+        root.add_child(Node::default());
+        root.add_child(Node::default());
+        root.children[0].add_child(Node::default());
+        root.children[0].add_child(Node::default());
+        root.children[0].rating = Some(1);
+        root.children[1].children.push(Node::default());
+        root.children[1].children[0].rating = Some(2);
+        root.children[1].children.push(Node::default());
+
+        let removed = root.prune(&|node| node.rating.map_or(true, |r| r >= 2));
+        assert_eq!(removed, 3);
+        assert_eq!(root.count(), 4);
+        assert_eq!(root.children[0].children.len(), 2);
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut root = Node::<Meta>::default();
+        root.add_child(Node::default());
+        root.add_child(Node::default());
+        root.children[0].add_child(Node::default());
+        assert!(root.children[0].children[0].add_tag(" canon "));
+        assert!(!root.children[0].children[0].add_tag("canon"));
+        assert!(!root.children[0].children[0].add_tag(""));
+
+        let filter = Filter::default();
+        assert!(filter.matches(&root) && filter.matches_subtree(&root));
+
+        let filter = Filter {
+            tag: Some("canon".to_string()),
+            ..Filter::default()
+        };
+        assert!(!filter.matches(&root));
+        assert!(filter.matches_subtree(&root));
+        assert!(filter.matches_subtree(&root.children[0]));
+        assert!(!filter.matches_subtree(&root.children[1]));
+        assert!(filter.matches(&root.children[0].children[0]));
+
+        // Shown nodes are found in one pass.
+        let visible = filter.matcher().visible(&root);
+        let id =
+            |path: &[usize]| root.iter_path_nodes(path).last().unwrap().meta.id;
+        assert_eq!(visible.matched, [id(&[0, 0])].into());
+        assert_eq!(visible.shown, [id(&[]), id(&[0]), id(&[0, 0])].into());
+    }
 }
@@ -0,0 +1,473 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    node::{Meta, Node},
+    story::Story,
+};
+
+/// Characters of context to either side of a match in a [`Hit::preview`].
+pub const PREVIEW_CONTEXT: usize = 32;
+
+/// A text search over node text.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Query {
+    /// Text, or pattern if `regex` is set, to search for.
+    pub text: String,
+    /// Whether `text` is a regular expression.
+    pub regex: bool,
+    /// Whether the search is case sensitive.
+    pub case_sensitive: bool,
+}
+
+static_assertions::assert_impl_all!(Query: Send, Sync);
+
+impl Query {
+    /// Compile the query. This fails if `regex` is set and `text` is not a
+    /// valid regular expression.
+    pub fn compile(&self) -> Result<regex::Regex, regex::Error> {
+        let pattern = if self.regex {
+            std::borrow::Cow::Borrowed(self.text.as_str())
+        } else {
+            std::borrow::Cow::Owned(regex::escape(&self.text))
+        };
+
+        regex::RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+    }
+
+    /// Draw the query editor. The response is changed if the query changed.
+    #[cfg(feature = "gui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> egui::Response {
+        let mut response = ui.add(
+            egui::TextEdit::singleline(&mut self.text)
+                .hint_text("Search")
+                .desired_width(f32::INFINITY),
+        );
+        ui.horizontal(|ui| {
+            let regex = ui.checkbox(&mut self.regex, "Regex");
+            let case = ui.checkbox(&mut self.case_sensitive, "Match case");
+            if regex.changed() || case.changed() {
+                response.mark_changed();
+            }
+        });
+        response
+    }
+}
+
+/// Whether a [`Hit`] is in an open story or in the trash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Stories,
+    Trash,
+}
+
+/// Which part of a node a [`Hit`] is in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// The node's text.
+    Text,
+    /// One of the node's tags, by index.
+    Tag(usize),
+    /// The node's notes.
+    Notes,
+}
+
+/// A match of a [`Query`] in some node's text, tags or notes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hit {
+    /// Which list of stories `story` indexes.
+    pub source: Source,
+    /// Index of the story.
+    pub story: usize,
+    /// Path from the root to the matching node.
+    pub path: Vec<usize>,
+    /// Where in the node the match is.
+    pub field: Field,
+    /// Byte range of the match in the `field`.
+    pub range: Range<usize>,
+    /// The match with some surrounding context, on one line.
+    pub preview: String,
+    /// Byte range of the match in `preview`.
+    pub preview_range: Range<usize>,
+}
+
+static_assertions::assert_impl_all!(Hit: Send, Sync);
+
+/// Search the text, tags and notes of every node of a `story`. Hits are
+/// returned in depth-first order and have their `source` set to
+/// [`Source::Stories`].
+pub fn search_story(
+    story: &Story,
+    index: usize,
+    regex: &regex::Regex,
+) -> Vec<Hit> {
+    let mut hits = Vec::new();
+    for (path, node) in story.root().iter_depth_first_paths() {
+        let fields = std::iter::once((Field::Text, &node.text))
+            .chain(
+                node.tags
+                    .iter()
+                    .enumerate()
+                    .map(|(i, tag)| (Field::Tag(i), tag)),
+            )
+            .chain(std::iter::once((Field::Notes, &node.notes)));
+        for (field, text) in fields {
+            for m in regex.find_iter(text) {
+                // Empty patterns match everywhere, which isn't useful.
+                if m.is_empty() {
+                    continue;
+                }
+                let (preview, preview_range) = preview(text, m.range());
+                hits.push(Hit {
+                    source: Source::Stories,
+                    story: index,
+                    path: path.clone(),
+                    field,
+                    range: m.range(),
+                    preview,
+                    preview_range,
+                });
+            }
+        }
+    }
+    hits
+}
+
+/// Search all `stories` and the `trash`.
+pub fn search(
+    stories: &[Story],
+    trash: &[Story],
+    regex: &regex::Regex,
+) -> Vec<Hit> {
+    let mut hits = Vec::new();
+    for (i, story) in stories.iter().enumerate() {
+        hits.extend(search_story(story, i, regex));
+    }
+    for (i, story) in trash.iter().enumerate() {
+        hits.extend(search_story(story, i, regex).into_iter().map(|hit| Hit {
+            source: Source::Trash,
+            ..hit
+        }));
+    }
+    hits
+}
+
+/// Which nodes of a story a find and replace applies to.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    /// Nodes from the root to the head.
+    #[default]
+    ActivePath,
+    /// The head and all its descendants.
+    Subtree,
+    /// Every node in the story.
+    Story,
+}
+
+impl Scope {
+    pub const ALL: [Self; 3] = [Self::ActivePath, Self::Subtree, Self::Story];
+
+    pub const fn as_str(&self) -> &str {
+        match self {
+            Self::ActivePath => "Active path",
+            Self::Subtree => "Subtree",
+            Self::Story => "Whole story",
+        }
+    }
+}
+
+/// A pending replacement of a match in a story.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replacement {
+    /// Path from the root to the node.
+    pub path: Vec<usize>,
+    /// Byte range of the match in the node's text.
+    pub range: Range<usize>,
+    /// The matched text. If the node's text no longer has it at `range`, the
+    /// replacement is stale and isn't applied.
+    pub matched: String,
+    /// The replacement text, with any capture groups expanded.
+    pub with: String,
+    /// The match with some surrounding context, on one line.
+    pub preview: String,
+    /// Byte range of the match in `preview`.
+    pub preview_range: Range<usize>,
+    /// Whether the replacement should be applied.
+    pub enabled: bool,
+}
+
+static_assertions::assert_impl_all!(Replacement: Send, Sync);
+
+/// Find matches of `regex` in a `scope` of a `story`. Each is to be replaced
+/// `with` a string in which `$1`, `$name` and so on are expanded, as with
+/// [`regex::Regex::replace`].
+pub fn find_replacements(
+    story: &Story,
+    scope: Scope,
+    regex: &regex::Regex,
+    with: &str,
+) -> Vec<Replacement> {
+    let active_path = story.active_path();
+    let nodes: Vec<(Vec<usize>, &Node<Meta>)> = match scope {
+        Scope::ActivePath => story
+            .iter_active_path()
+            .enumerate()
+            .map(|(depth, node)| (active_path[..depth].to_vec(), node))
+            .collect(),
+        Scope::Subtree => story
+            .head()
+            .iter_depth_first_paths()
+            .map(|(path, node)| ([active_path, &path].concat(), node))
+            .collect(),
+        Scope::Story => story.root().iter_depth_first_paths().collect(),
+    };
+
+    let mut replacements = Vec::new();
+    for (path, node) in nodes {
+        for captures in regex.captures_iter(&node.text) {
+            let m = captures.get(0).unwrap();
+            if m.is_empty() {
+                continue;
+            }
+            let mut expanded = String::new();
+            captures.expand(with, &mut expanded);
+            let (preview, preview_range) = preview(&node.text, m.range());
+            replacements.push(Replacement {
+                path: path.clone(),
+                range: m.range(),
+                matched: m.as_str().to_string(),
+                with: expanded,
+                preview,
+                preview_range,
+                enabled: true,
+            });
+        }
+    }
+    replacements
+}
+
+/// Apply the enabled `replacements` to a `story`, adjusting piece offsets.
+/// Replacements whose matched text has since changed are skipped. Returns the
+/// number of replacements applied.
+pub fn apply_replacements(
+    story: &mut Story,
+    replacements: &[Replacement],
+) -> usize {
+    let mut by_node: std::collections::BTreeMap<&[usize], Vec<_>> =
+        Default::default();
+    for r in replacements.iter().filter(|r| r.enabled) {
+        by_node.entry(r.path.as_slice()).or_default().push((
+            r.range.clone(),
+            r.matched.as_str(),
+            r.with.as_str(),
+        ));
+    }
+
+    let mut count = 0;
+    for (path, mut ranges) in by_node {
+        let Some(node) = story.node_mut(path) else {
+            continue;
+        };
+        ranges.sort_by_key(|(range, _, _)| range.start);
+        // Skip ranges that are stale or overlapping.
+        let mut end = 0;
+        ranges.retain(|(range, matched, _)| {
+            let valid = range.start >= end
+                && node.text.get(range.clone()) == Some(*matched);
+            if valid {
+                end = range.end;
+            }
+            valid
+        });
+        count += ranges.len();
+        node.replace_ranges(
+            ranges.into_iter().map(|(range, _, with)| (range, with)),
+        );
+    }
+    count
+}
+
+/// Cut out `range` of `text` with up to [`PREVIEW_CONTEXT`] characters to
+/// either side. Newlines are replaced with spaces. Returns the preview and the
+/// range of the match within it.
+fn preview(text: &str, range: Range<usize>) -> (String, Range<usize>) {
+    let start = text[..range.start]
+        .char_indices()
+        .rev()
+        .nth(PREVIEW_CONTEXT - 1)
+        .map_or(0, |(i, _)| i);
+    let end = text[range.end..]
+        .char_indices()
+        .nth(PREVIEW_CONTEXT)
+        .map_or(text.len(), |(i, _)| range.end + i);
+
+    let mut preview = String::new();
+    if start > 0 {
+        preview.push('…');
+    }
+    let prefix = preview.len();
+    preview.extend(text[start..end].chars().map(|c| match c {
+        '\n' | '\r' => ' ',
+        c => c,
+    }));
+    if end < text.len() {
+        preview.push('…');
+    }
+
+    let range = range.start - start + prefix..range.end - start + prefix;
+    (preview, range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stories() -> (Vec<Story>, Vec<Story>) {
+        let mut a = Story::new("A".to_string(), "Alice".to_string());
+        a.add_paragraph("Alice", ["The cat sat.\n"]);
+        a.add_paragraph("Alice", ["The Cat ran. The cat hid."]);
+        let mut b = Story::new("B".to_string(), "Bob".to_string());
+        b.add_paragraph("Bob", ["A dog."]);
+        let mut c = Story::new("C".to_string(), "Carol".to_string());
+        c.add_paragraph("Carol", ["cat (deleted)"]);
+        (vec![a, b], vec![c])
+    }
+
+    #[test]
+    fn test_search() {
+        let (stories, trash) = stories();
+        let query = Query {
+            text: "cat".to_string(),
+            ..Query::default()
+        };
+        let hits = search(&stories, &trash, &query.compile().unwrap());
+        assert_eq!(hits.len(), 4);
+        assert_eq!(hits[0].path, vec![0]);
+        assert_eq!(hits[1].path, vec![0, 0]);
+        assert_eq!(hits[1].range, 4..7);
+        assert_eq!(hits[2].range, 17..20);
+        assert_eq!(hits[3].source, Source::Trash);
+        assert_eq!(hits[3].story, 0);
+
+        let query = Query {
+            case_sensitive: true,
+            ..query
+        };
+        let hits = search(&stories, &trash, &query.compile().unwrap());
+        assert_eq!(hits.len(), 3);
+    }
+
+    #[test]
+    fn test_search_annotations() {
+        let (mut stories, _) = stories();
+        let node = stories[1].head_mut();
+        node.add_tag("cat-lovers");
+        node.notes = "Needs a cat.".to_string();
+        let query = Query {
+            text: "cat".to_string(),
+            ..Query::default()
+        };
+        let hits = search_story(&stories[1], 1, &query.compile().unwrap());
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].field, Field::Tag(0));
+        assert_eq!(hits[1].field, Field::Notes);
+        assert_eq!(hits[1].range, 8..11);
+    }
+
+    #[test]
+    fn test_search_regex() {
+        let (stories, trash) = stories();
+        let mut query = Query {
+            text: r"(sat|dog)\.".to_string(),
+            ..Query::default()
+        };
+        // Plain text matches the pattern literally.
+        assert!(search(&stories, &trash, &query.compile().unwrap()).is_empty());
+
+        query.regex = true;
+        let hits = search(&stories, &trash, &query.compile().unwrap());
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[1].story, 1);
+
+        query.text = "(".to_string();
+        assert!(query.compile().is_err());
+    }
+
+    #[test]
+    fn test_preview() {
+        let text = "x".repeat(100) + "needle\nand" + &"y".repeat(100);
+        let (preview, range) = preview(&text, 100..106);
+        assert_eq!(&preview[range], "needle");
+        assert!(preview.starts_with('…'));
+        assert!(preview.ends_with('…'));
+        assert!(preview.contains("needle and"));
+
+        let (preview, range) = super::preview("é needle", 3..9);
+        assert_eq!(preview, "é needle");
+        assert_eq!(&preview[range], "needle");
+    }
+
+    #[test]
+    fn test_find_replace() {
+        let mut story = Story::new("A".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["Bob", " met", " Bob."]);
+        story.add_paragraph("Alice", ["Bob", " left."]);
+        story.add_paragraph("Alice", ["Bob?"]);
+        let node = story.node_mut(&[0]).unwrap();
+        node.add_child(Node::default());
+        node.children[1].extend_strings(["Not Bob."]);
+        assert!(story.set_active_path(vec![0, 0]));
+
+        let regex = Query {
+            text: "bob".to_string(),
+            ..Query::default()
+        }
+        .compile()
+        .unwrap();
+
+        let path = find_replacements(&story, Scope::ActivePath, &regex, "Rob");
+        assert_eq!(path.len(), 3);
+        let subtree = find_replacements(&story, Scope::Subtree, &regex, "Rob");
+        assert_eq!(subtree.len(), 2);
+        assert_eq!(subtree[0].path, vec![0, 0]);
+        assert_eq!(subtree[1].path, vec![0, 0, 0]);
+        let mut all = find_replacements(&story, Scope::Story, &regex, "Rob");
+        assert_eq!(all.len(), 5);
+
+        all[1].enabled = false;
+        assert_eq!(apply_replacements(&mut story, &all), 4);
+        let node = story.node_mut(&[0]).unwrap();
+        assert_eq!(node.text, "Rob met Bob.");
+        let pieces: Vec<_> = node.iter_pieces().collect();
+        assert_eq!(pieces, vec!["Rob", " met", " Bob."]);
+        assert_eq!(story.node_mut(&[0, 1]).unwrap().text, "Not Rob.");
+
+        // Capture groups are expanded.
+        assert!(story.set_active_path(vec![0]));
+        let regex = regex::Regex::new(r"(\w+) met (\w+)").unwrap();
+        let swap =
+            find_replacements(&story, Scope::ActivePath, &regex, "$2 met $1");
+        assert_eq!(swap[0].with, "Bob met Rob");
+        assert_eq!(apply_replacements(&mut story, &swap), 1);
+        assert_eq!(story.head().text, "Bob met Rob.");
+    }
+
+    #[test]
+    fn test_replace_stale() {
+        let mut story = Story::new("A".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["Bob met Bob."]);
+        let regex = regex::Regex::new("Bob").unwrap();
+        let replacements =
+            find_replacements(&story, Scope::Story, &regex, "Rob");
+        assert_eq!(replacements.len(), 2);
+
+        // The node is edited between finding and applying. Only the match
+        // that is still where it was found is replaced.
+        story.node_mut(&[0]).unwrap().text = "Bob met Ann.".to_string();
+        assert_eq!(apply_replacements(&mut story, &replacements), 1);
+        assert_eq!(story.head().text, "Rob met Ann.");
+    }
+}
@@ -2,17 +2,20 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
-use crate::node::{Meta, Node};
+use crate::node::{AuthorIndex, Meta, Node};
 
 #[derive(derive_more::From)]
 pub enum AuthorID {
     String(String),
-    ID(u8),
+    ID(AuthorIndex),
 }
 
 #[cfg(feature = "gui")]
 pub enum DrawMode {
-    /// Draw story as nodes, as usual.
+    /// Draw story as nodes, as usual. The [`egui::Ui`] should be on a
+    /// [`Canvas`].
+    ///
+    /// [`Canvas`]: crate::canvas::Canvas
     Nodes,
     /// Draw story as a collapsible tree.
     Tree,
@@ -26,17 +29,312 @@ impl From<&str> for AuthorID {
 
 static_assertions::assert_impl_all!(AuthorID: Send, Sync);
 
+/// Whether an author is a person or a generative model.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub enum AuthorRole {
+    /// A person.
+    #[default]
+    Human,
+    /// A generative model.
+    Model,
+}
+
+impl AuthorRole {
+    /// Get the role as a string.
+    pub const fn as_str(&self) -> &str {
+        match self {
+            Self::Human => "human",
+            Self::Model => "model",
+        }
+    }
+}
+
+/// Per-author metadata.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AuthorProfile {
+    /// Name shown in the UI and in reports. If empty, the author's name is
+    /// used. Unlike the name, this can be changed freely.
+    #[serde(default)]
+    pub display_name: String,
+    /// Color used to tint the author's nodes, if any.
+    #[serde(default)]
+    pub color: Option<[u8; 3]>,
+    /// Whether the author is a person or a model.
+    #[serde(default)]
+    pub role: AuthorRole,
+    /// Short badge shown on the author's nodes. If empty, this is derived from
+    /// the display name.
+    #[serde(default)]
+    pub initials: String,
+    /// Free-form notes about the author. These are never sent to a model.
+    #[serde(default)]
+    pub notes: String,
+}
+
+impl AuthorProfile {
+    /// Fill in the display name and initials if they are empty. `name` is the
+    /// author's name.
+    pub fn resolve(mut self, name: &str) -> Self {
+        if self.display_name.is_empty() {
+            self.display_name = if name.is_empty() {
+                crate::consts::DEFAULT_AUTHOR.to_string()
+            } else {
+                name.to_string()
+            };
+        }
+        if self.initials.is_empty() {
+            self.initials = initials(&self.display_name);
+        }
+        self
+    }
+
+    /// Color as an [`egui::Color32`], if any.
+    #[cfg(feature = "gui")]
+    pub fn color32(&self) -> Option<egui::Color32> {
+        self.color.map(|[r, g, b]| egui::Color32::from_rgb(r, g, b))
+    }
+
+    /// Draw an editor for the profile. `name` is the author's name, which is
+    /// used as a hint for empty fields.
+    #[cfg(feature = "gui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui, name: &str) -> egui::Response {
+        let mut resp = ui
+            .horizontal(|ui| {
+                ui.label("Display name:")
+                    | ui.add(
+                        egui::TextEdit::singleline(&mut self.display_name)
+                            .hint_text(name),
+                    )
+            })
+            .inner;
+        resp |= ui
+            .horizontal(|ui| {
+                ui.label("Initials:")
+                    | ui.add(
+                        egui::TextEdit::singleline(&mut self.initials)
+                            .char_limit(3)
+                            .desired_width(32.0)
+                            .hint_text(initials(
+                                if self.display_name.is_empty() {
+                                    name
+                                } else {
+                                    &self.display_name
+                                },
+                            )),
+                    )
+            })
+            .inner;
+        resp |= ui
+            .horizontal(|ui| {
+                let mut tinted = self.color.is_some();
+                let mut resp = ui
+                    .checkbox(&mut tinted, "Color")
+                    .on_hover_text_at_pointer("Tint this author's nodes.");
+                if tinted {
+                    let color = self.color.get_or_insert([128, 128, 255]);
+                    resp |= ui.color_edit_button_srgb(color);
+                } else {
+                    self.color = None;
+                }
+                resp
+            })
+            .inner;
+        resp |= ui
+            .horizontal(|ui| {
+                ui.label("Role:");
+                ui.selectable_value(
+                    &mut self.role,
+                    AuthorRole::Human,
+                    AuthorRole::Human.as_str(),
+                ) | ui.selectable_value(
+                    &mut self.role,
+                    AuthorRole::Model,
+                    AuthorRole::Model.as_str(),
+                )
+            })
+            .inner;
+        ui.label("Notes:");
+        resp | ui.text_edit_multiline(&mut self.notes)
+    }
+}
+
+/// Derive up to two initials from a name, for example "Jane Doe" -> "JD".
+fn initials(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter_map(|word| word.chars().next())
+        .take(2)
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// The child of `node` most recently on the active path, if it still exists,
+/// or else the first child.
+#[cfg(feature = "gui")]
+fn last_child(node: &Node<Meta>) -> Option<usize> {
+    match node.meta.last_child {
+        Some(i) if i < node.children.len() => Some(i),
+        _ => (!node.children.is_empty()).then_some(0),
+    }
+}
+
+/// A subtree cut from a [`Story`]. Author ids only have meaning within a
+/// story, so the clipping carries the names and profiles of the source story's
+/// authors. This way authors can be remapped when pasted into another story.
+#[derive(Clone)]
+pub struct Clipping {
+    /// Author names and profiles of the source story, indexed by id.
+    authors: Vec<(String, AuthorProfile)>,
+    /// The root of the subtree.
+    pub node: Node<Meta>,
+}
+
+static_assertions::assert_impl_all!(Clipping: Send, Sync);
+
+/// A named node in a [`Story`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    /// Id of the bookmarked node. Ids are stable across edits, unlike paths.
+    pub id: u128,
+}
+
+static_assertions::assert_impl_all!(Bookmark: Send, Sync);
+
+/// Maximum number of entries in each direction of a [`History`].
+pub const HISTORY_LIMIT: usize = 100;
+
+/// Navigation history of a [`Story`]'s head, by node id, so that it survives
+/// changes to the tree.
+#[cfg(feature = "gui")]
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    back: Vec<u128>,
+    forward: Vec<u128>,
+}
+
+#[cfg(feature = "gui")]
+impl History {
+    /// Record leaving the node with `id`. This clears the forward history.
+    pub fn leave(&mut self, id: u128) {
+        if self.back.last() != Some(&id) {
+            self.back.push(id);
+            if self.back.len() > HISTORY_LIMIT {
+                self.back.remove(0);
+            }
+        }
+        self.forward.clear();
+    }
+
+    /// Returns true if there is somewhere to go back to.
+    pub fn can_go_back(&self) -> bool {
+        !self.back.is_empty()
+    }
+
+    /// Returns true if there is somewhere to go forward to.
+    pub fn can_go_forward(&self) -> bool {
+        !self.forward.is_empty()
+    }
+}
+
+/// A [`Story::move_node`] that can be undone, by node id so that it survives
+/// changes to the tree.
+#[cfg(feature = "gui")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Move {
+    /// The node that was moved.
+    id: u128,
+    /// Its parent before the move.
+    parent: u128,
+    /// Its index among the parent's children before the move.
+    index: usize,
+}
+
+/// Why a node can't be moved to a new parent.
+#[cfg(feature = "gui")]
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum MoveError {
+    #[error("The root node can't be moved.")]
+    Root,
+    #[error("A node can't be moved into itself or its own descendants.")]
+    Descendant,
+    #[error("The node is no longer in the story.")]
+    NotFound,
+}
+
+/// A move of the head to a node related to it.
+#[cfg(feature = "gui")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Parent,
+    /// The child most recently on the active path, or the first child.
+    Child,
+    FirstSibling,
+    PreviousSibling,
+    NextSibling,
+    /// The leaf reached by repeatedly taking a [`Step::Child`].
+    Deepest,
+}
+
 #[derive(Default, Serialize, Deserialize)]
 pub struct Story {
     active_path: Option<Vec<usize>>,
     pub title: String,
-    author_to_id: HashMap<String, u8>,
+    author_to_id: HashMap<String, AuthorIndex>,
     id_to_author: Vec<String>,
+    /// Author profiles, indexed by author id. Older stories don't have these,
+    /// so an author may be missing a profile, in which case the default is
+    /// used.
+    #[serde(default)]
+    author_profiles: Vec<AuthorProfile>,
+    /// Named nodes, in the order they were added.
+    #[serde(default)]
+    bookmarks: Vec<Bookmark>,
+    /// Where the head has been. This isn't saved.
+    #[cfg(feature = "gui")]
+    #[serde(skip)]
+    history: History,
+    /// Moves that can be undone, most recent last. These aren't saved.
+    #[cfg(feature = "gui")]
+    #[serde(skip)]
+    moves: Vec<Move>,
+    /// Name of the story's file in the [`Library`], once it has one.
+    ///
+    /// [`Library`]: crate::library::Library
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    pub(crate) library_file: Option<String>,
+    /// JSON file the story was loaded from or last saved to, if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    path: Option<std::path::PathBuf>,
+    /// [`Story::fingerprint`] when the story was last saved or loaded.
+    #[serde(skip)]
+    saved: Option<u64>,
+    /// Whether the story had changed since it was saved, as of the last
+    /// [`Story::check_dirty`].
+    #[serde(skip)]
+    dirty: bool,
     root: Node<Meta>,
 }
 
 static_assertions::assert_impl_all!(Story: Send, Sync);
 
+/// Hashes what is written to it.
+struct HashWriter(std::collections::hash_map::DefaultHasher);
+
+impl std::io::Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        std::hash::Hasher::write(&mut self.0, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 impl Story {
     pub fn new(title: String, author: String) -> Self {
         let mut new = Self {
@@ -44,11 +342,101 @@ impl Story {
             ..Self::default()
         };
 
+        // The first author always fits.
         new.add_author(author);
 
         new
     }
 
+    /// A hash of the story's content: its title, authors, bookmarks and nodes,
+    /// including which nodes are pinned. Node positions and sizes, which the
+    /// layout changes by itself, and the active path are left out, so they
+    /// don't change it. If it differs, the story has been edited.
+    pub fn fingerprint(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        let mut writer = HashWriter(Default::default());
+        // Serializing to a writer that can't fail can't fail.
+        serde_json::to_writer(
+            &mut writer,
+            &(
+                &self.title,
+                &self.id_to_author,
+                &self.author_profiles,
+                &self.bookmarks,
+            ),
+        )
+        .unwrap();
+        let hasher = &mut writer.0;
+        for node in self.root.iter_depth_first() {
+            node.author_id.hash(hasher);
+            node.text.hash(hasher);
+            for piece in &node.pieces {
+                piece.end.hash(hasher);
+            }
+            node.rating.hash(hasher);
+            node.flags.hash(hasher);
+            node.tags.hash(hasher);
+            node.notes.hash(hasher);
+            node.children.len().hash(hasher);
+            #[cfg(feature = "gui")]
+            node.meta.pinned.hash(hasher);
+        }
+        writer.0.finish()
+    }
+
+    /// A hash of everything about the story that is saved, layout and the
+    /// active path included. If it differs, the story's file is out of date.
+    /// Unlike [`Story::fingerprint`], this serializes the whole story.
+    pub fn file_hash(&self) -> u64 {
+        use std::hash::Hasher;
+
+        let mut writer = HashWriter(Default::default());
+        // Serializing to a writer that can't fail can't fail.
+        serde_json::to_writer(&mut writer, self).unwrap();
+        writer.0.finish()
+    }
+
+    /// Record that the story was saved. `fingerprint` is its current
+    /// [`Story::fingerprint`], which the caller has usually just computed.
+    pub fn mark_saved(&mut self, fingerprint: u64) {
+        self.dirty = false;
+        self.saved = Some(fingerprint);
+    }
+
+    /// The [`Story::fingerprint`] as of the last save, if there was one.
+    pub fn saved_fingerprint(&self) -> Option<u64> {
+        self.saved
+    }
+
+    /// Check whether the story has changed since it was last saved. Stories
+    /// that have never been saved have changed. This hashes the whole story,
+    /// so it shouldn't be called every frame.
+    pub fn check_dirty(&mut self) -> bool {
+        self.dirty = self.saved != Some(self.fingerprint());
+        self.dirty
+    }
+
+    /// Whether the story had unsaved changes as of the last
+    /// [`Story::check_dirty`] or save.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// JSON file the story was loaded from or last saved to, if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn path(&self) -> Option<&std::path::Path> {
+        self.path.as_deref()
+    }
+
+    /// Set the JSON file the story is saved to. If the file exists, the path
+    /// is canonicalized, since the same file can be named many ways.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_path(&mut self, path: impl Into<std::path::PathBuf>) {
+        let path = path.into();
+        self.path = Some(path.canonicalize().unwrap_or(path));
+    }
+
     /// Get the head node of the story. `head` is like git's `HEAD` and
     /// represents the current node the story is at.
     pub fn head(&self) -> &Node<Meta> {
@@ -73,28 +461,32 @@ impl Story {
     }
 
     /// Add an author to the story. If the author already exists, return their
-    /// id.
-    pub fn add_author(&mut self, author: impl Into<String>) -> u8 {
+    /// id. Returns None if the story already has as many authors as an
+    /// [`AuthorIndex`] can count.
+    pub fn add_author(
+        &mut self,
+        author: impl Into<String>,
+    ) -> Option<AuthorIndex> {
         let author: String = author.into();
         if let Some(&id) = self.author_to_id.get(&author) {
-            id
+            Some(id)
         } else {
-            let new_id = self.id_to_author.len() as u8;
+            let new_id = AuthorIndex::try_from(self.id_to_author.len()).ok()?;
             self.id_to_author.push(author.clone());
             self.author_to_id.insert(author, new_id);
-            new_id
+            Some(new_id)
         }
     }
 
     /// Get id for an author. If the author doesn't exist, return None.
-    pub fn get_author<Id>(&self, author: Id) -> Option<u8>
+    pub fn get_author<Id>(&self, author: Id) -> Option<AuthorIndex>
     where
         Id: Into<AuthorID>,
     {
         match author.into() {
             AuthorID::String(author) => self.author_to_id.get(&author).copied(),
             AuthorID::ID(id) => {
-                if id < self.id_to_author.len() as u8 {
+                if (id as usize) < self.id_to_author.len() {
                     Some(id)
                 } else {
                     None
@@ -104,21 +496,682 @@ impl Story {
     }
 
     /// Iterate over author ids and names.
-    pub fn authors(&self) -> impl Iterator<Item = (u8, &str)> {
+    pub fn authors(&self) -> impl Iterator<Item = (AuthorIndex, &str)> {
         self.id_to_author
             .iter()
             .enumerate()
-            .map(|(id, author)| (id as u8, author.as_str()))
+            .map(|(id, author)| (id as AuthorIndex, author.as_str()))
+    }
+
+    /// Get an author's name by id. If the author doesn't exist, return None.
+    pub fn author_name(&self, id: AuthorIndex) -> Option<&str> {
+        self.id_to_author.get(id as usize).map(String::as_str)
+    }
+
+    /// Get an author's profile with the display name and initials filled in.
+    /// If the author has no profile yet, the default is used.
+    pub fn author_profile(&self, id: AuthorIndex) -> AuthorProfile {
+        self.stored_author_profile(id)
+            .resolve(self.author_name(id).unwrap_or_default())
+    }
+
+    /// Get an author's profile as stored, without anything filled in.
+    fn stored_author_profile(&self, id: AuthorIndex) -> AuthorProfile {
+        self.author_profiles
+            .get(id as usize)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Get the profiles of all authors, indexed by id, with display names and
+    /// initials filled in.
+    pub fn author_profiles(&self) -> Vec<AuthorProfile> {
+        self.authors()
+            .map(|(id, _)| self.author_profile(id))
+            .collect()
+    }
+
+    /// Get a mutable author profile, creating profiles as needed. Returns None
+    /// if the author doesn't exist.
+    pub fn author_profile_mut(
+        &mut self,
+        id: AuthorIndex,
+    ) -> Option<&mut AuthorProfile> {
+        if id as usize >= self.id_to_author.len() {
+            return None;
+        }
+        if self.author_profiles.len() <= id as usize {
+            self.author_profiles
+                .resize_with(id as usize + 1, Default::default);
+        }
+        self.author_profiles.get_mut(id as usize)
+    }
+
+    /// Add a generative model as an author. If the author already exists,
+    /// return their id. Either way, the author is marked as a model. Returns
+    /// None if there are too many authors, as [`Story::add_author`] does.
+    pub fn add_model_author(
+        &mut self,
+        model: impl Into<String>,
+    ) -> Option<AuthorIndex> {
+        let id = self.add_author(model)?;
+        if let Some(profile) = self.author_profile_mut(id) {
+            profile.role = AuthorRole::Model;
+        }
+        Some(id)
+    }
+
+    /// Get the active path, from the root to the head. The root's path is
+    /// empty.
+    pub fn active_path(&self) -> &[usize] {
+        self.active_path.as_deref().unwrap_or_default()
+    }
+
+    /// Set the active path, making the node at `path` the head. The previous
+    /// head is recorded in the navigation history. Returns false and leaves
+    /// the active path unchanged if `path` is invalid.
+    pub fn set_active_path(&mut self, path: Vec<usize>) -> bool {
+        if self.root.is_valid_path(&path) {
+            self.navigate(path);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Make the node at a valid `path` the head, recording the previous head
+    /// in the history if it changed.
+    fn navigate(&mut self, path: Vec<usize>) {
+        #[cfg(feature = "gui")]
+        if self.active_path() != path.as_slice() {
+            let id = self.head().meta.id();
+            self.history.leave(id);
+            self.visit(&path);
+        }
+        self.active_path = Some(path);
+    }
+
+    /// Remember, for each node along a valid `path`, which child was taken.
+    #[cfg(feature = "gui")]
+    fn visit(&mut self, path: &[usize]) {
+        let mut node = &mut self.root;
+        for &i in path {
+            node.meta.last_child = Some(i);
+            node = &mut node.children[i];
+        }
+    }
+
+    /// Navigation history of the head.
+    #[cfg(feature = "gui")]
+    pub fn history(&self) -> &History {
+        &self.history
     }
 
-    /// Add a node to the story's head node.
+    /// Go back to where the head was before. Deleted nodes, and the head
+    /// itself, are skipped. Returns false if there is nowhere to go back to.
+    #[cfg(feature = "gui")]
+    pub fn go_back(&mut self) -> bool {
+        let head = self.head().meta.id();
+        while let Some(id) = self.history.back.pop() {
+            if id == head {
+                continue;
+            }
+            if let Some(path) = self.find_node(id) {
+                self.history.forward.push(head);
+                self.visit(&path);
+                self.active_path = Some(path);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Undo [`Story::go_back`]. Deleted nodes, and the head itself, are
+    /// skipped. Returns false if there is nowhere to go forward to.
+    #[cfg(feature = "gui")]
+    pub fn go_forward(&mut self) -> bool {
+        let head = self.head().meta.id();
+        while let Some(id) = self.history.forward.pop() {
+            if id == head {
+                continue;
+            }
+            if let Some(path) = self.find_node(id) {
+                self.history.back.push(head);
+                self.visit(&path);
+                self.active_path = Some(path);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Move the head one [`Step`]. Returns false if there is no such node, in
+    /// which case the head doesn't move.
+    #[cfg(feature = "gui")]
+    pub fn step(&mut self, step: Step) -> bool {
+        let mut path = self.active_path().to_vec();
+        let siblings = path.len().checked_sub(1).map(|depth| {
+            self.root
+                .iter_path_nodes(&path[..depth])
+                .last()
+                .map_or(0, |parent| parent.children.len())
+        });
+        match (step, path.last_mut(), siblings) {
+            (Step::Parent, Some(_), _) => {
+                path.pop();
+            }
+            (Step::Child, ..) => match last_child(self.head()) {
+                Some(i) => path.push(i),
+                None => return false,
+            },
+            (Step::FirstSibling, Some(i), _) if *i > 0 => *i = 0,
+            (Step::PreviousSibling, Some(i), _) if *i > 0 => *i -= 1,
+            (Step::NextSibling, Some(i), Some(n)) if *i + 1 < n => *i += 1,
+            (Step::Deepest, ..) => {
+                let mut node = self.head();
+                while let Some(i) = last_child(node) {
+                    path.push(i);
+                    node = &node.children[i];
+                }
+                if path.len() == self.active_path().len() {
+                    return false;
+                }
+            }
+            _ => return false,
+        }
+        self.navigate(path);
+        true
+    }
+
+    /// Set the active path by following the [`Node::best_child`] of each node
+    /// from the root to a leaf.
+    pub fn follow_best_path(&mut self) {
+        let mut path = Vec::new();
+        let mut node = &self.root;
+        while let Some(i) = node.best_child() {
+            path.push(i);
+            node = &node.children[i];
+        }
+        self.navigate(path);
+    }
+
+    /// Remove nodes for which `keep` returns false, along with their
+    /// children. The root is always kept. The active path is adjusted to
+    /// point at the same node, or its closest surviving ancestor. Returns the
+    /// number of nodes removed.
+    pub fn prune<F>(&mut self, keep: F) -> usize
+    where
+        F: Fn(&Node<Meta>) -> bool,
+    {
+        // Sibling indices shift as nodes are removed, so the new active path
+        // has to be worked out before pruning.
+        let mut new_path = Vec::new();
+        let mut node = &self.root;
+        for &i in self.active_path() {
+            let child = &node.children[i];
+            if !keep(child) {
+                break;
+            }
+            let removed_before =
+                node.children[..i].iter().filter(|c| !keep(c)).count();
+            new_path.push(i - removed_before);
+            node = child;
+        }
+
+        let removed = self.root.prune(&keep);
+        if self.active_path.is_some() {
+            self.active_path = Some(new_path);
+        }
+        removed
+    }
+
+    /// Remove rated nodes with a rating below `threshold`, along with their
+    /// children. Unrated nodes are kept.
+    pub fn prune_below(&mut self, threshold: u8) -> usize {
+        self.prune(|node| node.rating.map_or(true, |r| r >= threshold))
+    }
+
+    /// Remove nodes flagged as rejected, along with their children.
+    pub fn prune_rejected(&mut self) -> usize {
+        self.prune(|node| !node.flags.rejected)
+    }
+
+    /// Named bookmarks, in the order they were added.
+    pub fn bookmarks(&self) -> &[Bookmark] {
+        &self.bookmarks
+    }
+
+    /// Bookmark the head as `name`. An existing bookmark with the same name is
+    /// moved to the head.
+    #[cfg(feature = "gui")]
+    pub fn add_bookmark(&mut self, name: impl Into<String>) {
+        let name = name.into();
+        let id = self.head().meta.id();
+        match self.bookmarks.iter_mut().find(|b| b.name == name) {
+            Some(bookmark) => bookmark.id = id,
+            None => self.bookmarks.push(Bookmark { name, id }),
+        }
+    }
+
+    /// Remove the bookmark at `index`, if any.
+    pub fn remove_bookmark(&mut self, index: usize) -> Option<Bookmark> {
+        (index < self.bookmarks.len()).then(|| self.bookmarks.remove(index))
+    }
+
+    /// Path from the root to the node with `id`, if it's in the story.
+    #[cfg(feature = "gui")]
+    pub fn find_node(&self, id: u128) -> Option<Vec<usize>> {
+        self.root
+            .iter_depth_first_paths()
+            .find(|(_, node)| node.meta.id() == id)
+            .map(|(path, _)| path)
+    }
+
+    /// Move the node with `id`, and its descendants, to be the last child of
+    /// the node with id `parent`. The head stays the same node, so the active
+    /// path changes if the head was among those moved. The move can be undone
+    /// with [`Story::undo_move`]. Moving a node to its own parent does nothing.
+    #[cfg(feature = "gui")]
+    pub fn move_node(
+        &mut self,
+        id: u128,
+        parent: u128,
+    ) -> Result<(), MoveError> {
+        let path = self.find_node(id).ok_or(MoveError::NotFound)?;
+        let (&index, from) = path.split_last().ok_or(MoveError::Root)?;
+        let to = self.find_node(parent).ok_or(MoveError::NotFound)?;
+        if to.starts_with(&path) {
+            return Err(MoveError::Descendant);
+        }
+        if to == from {
+            return Ok(());
+        }
+
+        let parent = self.root.iter_path_nodes(from).last().unwrap().meta.id();
+        self.reparent(&path, &to, None);
+        self.moves.push(Move { id, parent, index });
+        if self.moves.len() > HISTORY_LIMIT {
+            self.moves.remove(0);
+        }
+        Ok(())
+    }
+
+    /// Returns true if there is a move to undo.
+    #[cfg(feature = "gui")]
+    pub fn can_undo_move(&self) -> bool {
+        !self.moves.is_empty()
+    }
+
+    /// Undo the last [`Story::move_node`], putting the node back where it was.
+    /// Moves of nodes that have since been deleted, or whose former parent
+    /// has, are skipped. Returns false if there was nothing to undo.
+    #[cfg(feature = "gui")]
+    pub fn undo_move(&mut self) -> bool {
+        while let Some(Move { id, parent, index }) = self.moves.pop() {
+            let (Some(path), Some(to)) =
+                (self.find_node(id), self.find_node(parent))
+            else {
+                continue;
+            };
+            if path.is_empty() || to.starts_with(&path) {
+                continue;
+            }
+            self.reparent(&path, &to, Some(index));
+            return true;
+        }
+        false
+    }
+
+    /// Move the node at a valid, non-empty `path` to be a child of the node at
+    /// `to`, at `index` or else last. `to` must not be within the moved
+    /// subtree. The head stays the same node.
+    #[cfg(feature = "gui")]
+    fn reparent(&mut self, path: &[usize], to: &[usize], index: Option<usize>) {
+        let head = self.head().meta.id();
+        let parent = self.root.iter_path_nodes(to).last().unwrap().meta.id();
+        let (&i, from) = path.split_last().unwrap();
+        let node = self.node_mut(from).unwrap().children.remove(i);
+        // Removing the node may have shifted the new parent.
+        let to = self.find_node(parent).unwrap();
+        let children = &mut self.node_mut(&to).unwrap().children;
+        let index = index.map_or(children.len(), |i| i.min(children.len()));
+        children.insert(index, node);
+
+        let path = self.find_node(head).unwrap_or_default();
+        self.visit(&path);
+        self.active_path = Some(path);
+    }
+
+    /// Make the node bookmarked at `index` the head. Returns false if there is
+    /// no such bookmark or the node has since been deleted.
+    #[cfg(feature = "gui")]
+    pub fn go_to_bookmark(&mut self, index: usize) -> bool {
+        match self
+            .bookmarks
+            .get(index)
+            .and_then(|bookmark| self.find_node(bookmark.id))
+        {
+            Some(path) => self.set_active_path(path),
+            None => false,
+        }
+    }
+
+    /// All tags used in the story, sorted.
+    pub fn tags(&self) -> std::collections::BTreeSet<&str> {
+        self.root
+            .iter_depth_first()
+            .flat_map(|node| node.tags.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// Unpin every node so the layout can move it. Returns the number of nodes
+    /// that were pinned.
+    #[cfg(feature = "gui")]
+    pub fn unpin_all(&mut self) -> usize {
+        self.root.unpin_all()
+    }
+
+    /// Paths to the nodes selected for bulk operations, in depth-first order.
+    #[cfg(feature = "gui")]
+    pub fn selected_paths(&self) -> Vec<Vec<usize>> {
+        self.root
+            .iter_depth_first_paths()
+            .filter(|(_, node)| node.meta.selected)
+            .map(|(path, _)| path)
+            .collect()
+    }
+
+    /// Number of selected nodes.
+    #[cfg(feature = "gui")]
+    pub fn selection_len(&self) -> usize {
+        self.root
+            .iter_depth_first()
+            .filter(|node| node.meta.selected)
+            .count()
+    }
+
+    /// Select the nodes that overlap `rect`, in canvas coordinates, in
+    /// addition to those already selected. Returns the number newly selected.
+    #[cfg(feature = "gui")]
+    pub fn select_within(&mut self, rect: egui::Rect) -> usize {
+        let mut count = 0;
+        let mut stack = vec![&mut self.root];
+        while let Some(node) = stack.pop() {
+            let bounds =
+                egui::Rect::from_min_size(node.meta.pos, node.meta.size);
+            if !node.meta.selected && bounds.intersects(rect) {
+                node.meta.selected = true;
+                count += 1;
+            }
+            stack.extend(node.children.iter_mut());
+        }
+        count
+    }
+
+    /// Deselect every node.
+    #[cfg(feature = "gui")]
+    pub fn clear_selection(&mut self) {
+        self.update_selected(|node| node.meta.selected = false);
+    }
+
+    /// Call `f` with each selected node. Returns the number of nodes.
+    #[cfg(feature = "gui")]
+    fn update_selected<F>(&mut self, mut f: F) -> usize
+    where
+        F: FnMut(&mut Node<Meta>),
+    {
+        let mut count = 0;
+        let mut stack = vec![&mut self.root];
+        while let Some(node) = stack.pop() {
+            if node.meta.selected {
+                f(node);
+                count += 1;
+            }
+            stack.extend(node.children.iter_mut());
+        }
+        count
+    }
+
+    /// Remove the selected nodes, along with their children, as with
+    /// [`Story::prune`]. The root is never removed but is deselected. Returns
+    /// the number of nodes removed.
+    #[cfg(feature = "gui")]
+    pub fn delete_selected(&mut self) -> usize {
+        let removed = self.prune(|node| !node.meta.selected);
+        self.root.meta.selected = false;
+        removed
+    }
+
+    /// Add a `tag` to the selected nodes. Returns the number of nodes that
+    /// didn't already have it.
+    #[cfg(feature = "gui")]
+    pub fn tag_selected(&mut self, tag: &str) -> usize {
+        let mut count = 0;
+        self.update_selected(|node| count += node.add_tag(tag) as usize);
+        count
+    }
+
+    /// Set the `rating` of the selected nodes. `None` clears it. Ratings are
+    /// capped at [`MAX_RATING`]. Returns the number of nodes.
+    ///
+    /// [`MAX_RATING`]: crate::node::MAX_RATING
+    #[cfg(feature = "gui")]
+    pub fn rate_selected(&mut self, rating: Option<u8>) -> usize {
+        let rating = rating.map(|r| r.clamp(1, crate::node::MAX_RATING));
+        self.update_selected(|node| node.rating = rating)
+    }
+
+    /// Set the author of the selected nodes, but not their children, to
+    /// `author`. Returns the number of nodes.
+    ///
+    /// # Panics
+    /// - If the author doesn't exist.
+    #[cfg(feature = "gui")]
+    pub fn set_selected_author(&mut self, author: AuthorIndex) -> usize {
+        assert!(self.author_name(author).is_some());
+        self.update_selected(|node| node.set_author(author))
+    }
+
+    /// Format each selected branch as Markdown, as with
+    /// [`Story::format_markdown`]. A branch is the path from the root to a
+    /// selected node. Branches are separated by a horizontal rule.
+    #[cfg(feature = "gui")]
+    pub fn format_selected_markdown<F>(
+        &self,
+        mut f: F,
+        include_notes: bool,
+    ) -> std::fmt::Result
+    where
+        F: std::fmt::Write,
+    {
+        for (i, path) in self.selected_paths().into_iter().enumerate() {
+            if i > 0 {
+                write!(f, "\n\n---\n\n")?;
+            }
+            // A branch is the active path of a story that ends at the node.
+            let branch = self.branch_to_story(&path, self.title.clone());
+            branch.format_markdown(&mut f, include_notes)?;
+        }
+        Ok(())
+    }
+
+    /// Format the active path as Markdown. Each node's notes, if
+    /// `include_notes` is set, follow it as a block quote.
+    pub fn format_markdown<F>(
+        &self,
+        mut f: F,
+        include_notes: bool,
+    ) -> std::fmt::Result
+    where
+        F: std::fmt::Write,
+    {
+        if !include_notes {
+            return self.format_full(f, true, true);
+        }
+
+        self.format_header(&mut f, true, true)?;
+        for (i, node) in self.iter_active_path().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", node)?;
+            if !node.notes.trim().is_empty() {
+                writeln!(f, "\n")?;
+                for line in node.notes.trim().lines() {
+                    writeln!(f, "> {}", line)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the root node of the story.
+    pub fn root(&self) -> &Node<Meta> {
+        &self.root
+    }
+
+    /// Get a mutable node by `path` from the root, if the path is valid.
+    pub fn node_mut(&mut self, path: &[usize]) -> Option<&mut Node<Meta>> {
+        let mut node = &mut self.root;
+        for &i in path {
+            node = node.children.get_mut(i)?;
+        }
+        Some(node)
+    }
+
+    /// Iterate over the nodes of the active path, from the root to the head.
+    pub fn iter_active_path(&self) -> impl Iterator<Item = &Node<Meta>> {
+        self.root.iter_path_nodes(self.active_path())
+    }
+
+    /// Add a node from this story to the story's head node. Author ids that
+    /// don't exist in this story are reset to the head's author. To move nodes
+    /// between stories, use [`Story::cut_head`] and [`Story::paste`] instead.
     pub fn paste_node(&mut self, mut node: Node<Meta>) {
-        // We do this for now to avoid a crash. We can't transfer author ids
-        // between stories yet, so we reset them to the head's author.
-        node.set_author(self.head().author_id);
+        let head_author = self.head().author_id;
+        let n_authors = self.id_to_author.len();
+        node.map_authors(|id| {
+            if (id as usize) < n_authors {
+                id
+            } else {
+                head_author
+            }
+        });
+        self.head_mut().add_child(node);
+    }
+
+    /// Wrap a `node` from this story in a [`Clipping`] along with the authors
+    /// so it can be pasted into any story.
+    pub fn clip(&self, node: Node<Meta>) -> Clipping {
+        Clipping {
+            authors: self
+                .authors()
+                .map(|(id, name)| {
+                    (name.to_string(), self.stored_author_profile(id))
+                })
+                .collect(),
+            node,
+        }
+    }
+
+    /// Remove the head (and all its children) and return it as a
+    /// [`Clipping`]. Returns None if the head is the root node.
+    pub fn cut_head(&mut self) -> Option<Clipping> {
+        let node = self.decapitate()?;
+        Some(self.clip(node))
+    }
+
+    /// Paste a [`Clipping`], possibly from another story, as a child of the
+    /// head. Authors are remapped by name and added if they don't exist yet.
+    pub fn paste(&mut self, clipping: Clipping) {
+        let Clipping { authors, mut node } = clipping;
+        let mut remapped: HashMap<AuthorIndex, AuthorIndex> = HashMap::new();
+        let head_author = self.head().author_id;
+        node.map_authors(|old| {
+            if let Some(&new) = remapped.get(&old) {
+                return new;
+            }
+            let new = match authors.get(old as usize) {
+                Some((name, profile)) => {
+                    let is_new = self.get_author(name.as_str()).is_none();
+                    // If there are too many authors, the head's author is
+                    // credited instead.
+                    let Some(new) = self.add_author(name.as_str()) else {
+                        remapped.insert(old, head_author);
+                        return head_author;
+                    };
+                    if is_new {
+                        if let Some(p) = self.author_profile_mut(new) {
+                            *p = profile.clone();
+                        }
+                    }
+                    new
+                }
+                // This shouldn't happen, but if it does, the head's author is
+                // a reasonable guess.
+                None => head_author,
+            };
+            remapped.insert(old, new);
+            new
+        });
         self.head_mut().add_child(node);
     }
 
+    /// Build a standalone story from a `path` in this story. The nodes along
+    /// the path (without siblings) become the new story's active path, and the
+    /// last node keeps all its children. Only authors of the copied nodes are
+    /// included. The copies get new node ids.
+    ///
+    /// If a part of the path is invalid, the copy stops at the last valid node.
+    pub fn branch_to_story(&self, path: &[usize], title: String) -> Story {
+        let mut new = Story {
+            title,
+            ..Self::default()
+        };
+
+        // Copy the path as a chain of nodes, from the deepest node up. The
+        // deepest node is a deep copy. The iterator always yields at least the
+        // root, so the unwrap is safe.
+        let nodes: Vec<&Node<Meta>> = self.root.iter_path_nodes(path).collect();
+        let depth = nodes.len() - 1;
+        let mut nodes = nodes.into_iter().rev();
+        let mut root = nodes.next().unwrap().clone();
+        for node in nodes {
+            let mut parent = node.clone_childless();
+            parent.add_child(root);
+            root = parent;
+        }
+        #[cfg(feature = "gui")]
+        root.renew_ids();
+
+        // Remap authors so only those used are included.
+        let mut remapped: HashMap<AuthorIndex, AuthorIndex> = HashMap::new();
+        root.map_authors(|old| {
+            *remapped.entry(old).or_insert_with(|| {
+                let name = self.author_name(old).unwrap_or_default();
+                // The new story has no more authors than this one, so they
+                // fit, but the first author is a reasonable guess if not.
+                let Some(id) = new.add_author(name) else {
+                    return 0;
+                };
+                if let Some(profile) = new.author_profile_mut(id) {
+                    *profile = self.stored_author_profile(old);
+                }
+                id
+            })
+        });
+
+        new.root = root;
+        new.active_path = if depth == 0 {
+            None
+        } else {
+            Some(vec![0; depth])
+        };
+
+        new
+    }
+
     /// Add paragraph to the story's head node.
     ///
     /// # Panics
@@ -135,6 +1188,10 @@ impl Story {
         let child_index = head.add_child(Node::with_author(author));
         let head = &mut head.children[child_index];
         head.extend_strings(strings);
+        #[cfg(feature = "gui")]
+        {
+            self.head_mut().meta.last_child = Some(child_index);
+        }
         if let Some(path) = &mut self.active_path {
             path.push(child_index);
         } else {
@@ -167,23 +1224,43 @@ impl Story {
         layout: crate::node::Layout,
         mode: DrawMode,
         time_step: f32,
+        filter: &crate::node::Filter,
     ) -> Option<crate::node::Action> {
         use crate::node::PathAction;
 
         let selected_path = self.active_path.as_ref().map(|v| v.as_slice());
+        let authors = self.author_profiles();
 
         // Draw, and update active path if changed.
         if let Some(PathAction { path, mut action }) = self.root.draw(
             ui,
             selected_path,
             lock_topology,
-            layout,
             mode,
             time_step,
+            crate::node::View::new(layout, &authors, filter),
         ) {
+            if let Some(id) = action.reparent {
+                // The node with `id` was dropped on the node at `path`. It
+                // becomes a child of that node, and the head.
+                if !lock_topology {
+                    let parent =
+                        self.root.iter_path_nodes(&path).last().unwrap();
+                    if self.move_node(id, parent.meta.id()).is_ok() {
+                        if let Some(path) = self.find_node(id) {
+                            self.navigate(path);
+                        }
+                        return Some(crate::node::Action {
+                            modified: true,
+                            ..Default::default()
+                        });
+                    }
+                }
+                return None;
+            }
             if !lock_topology {
                 // Any action unless we're locked should update the active path.
-                self.active_path = Some(path);
+                self.navigate(path);
             }
             // FIXME: as it turns out all the actions are mutually exclusive,
             // so we can probably use an enum rather than a struct. The user can
@@ -201,6 +1278,70 @@ impl Story {
         None
     }
 
+    /// Draw an editor for the story's authors. `new_author` is a buffer for
+    /// the name of an author to add.
+    ///
+    /// Returns true if the story has been modified.
+    #[cfg(feature = "gui")]
+    pub fn draw_authors(
+        &mut self,
+        ui: &mut egui::Ui,
+        new_author: &mut String,
+    ) -> bool {
+        let mut modified = false;
+        let head_author = self.head().author_id;
+        let mut assign = None;
+
+        for id in 0..self.id_to_author.len() as AuthorIndex {
+            let name = self.id_to_author[id as usize].clone();
+            let resolved = self.author_profile(id);
+            let title =
+                format!("{} ({})", resolved.display_name, resolved.initials);
+            egui::CollapsingHeader::new(title)
+                .id_source(("author", id))
+                .show(ui, |ui| {
+                    if !name.is_empty() {
+                        ui.label(format!("Name: {}", name));
+                    }
+                    if let Some(profile) = self.author_profile_mut(id) {
+                        modified |= profile.ui(ui, &name).changed();
+                    }
+                    if ui
+                        .add_enabled(
+                            id != head_author,
+                            egui::Button::new("Assign to selected node"),
+                        )
+                        .on_hover_text_at_pointer(
+                            "Credit this author for the selected node. Children are not affected.",
+                        )
+                        .clicked()
+                    {
+                        assign = Some(id);
+                    }
+                });
+        }
+
+        if let Some(id) = assign {
+            self.head_mut().author_id = id;
+            modified = true;
+        }
+
+        ui.horizontal(|ui| {
+            if crate::button!(ui, "../resources/add.png")
+                .on_hover_text_at_pointer("Add an author.")
+                .clicked()
+                && !new_author.is_empty()
+                && self.add_author(new_author.as_str()).is_some()
+            {
+                new_author.clear();
+                modified = true;
+            }
+            ui.text_edit_singleline(new_author);
+        });
+
+        modified
+    }
+
     /// Remove the head as well as all its children.
     ///
     /// Note: The root node is never removed.
@@ -231,6 +1372,61 @@ impl Story {
         include_authors: bool,
         include_title: bool,
     ) -> std::fmt::Result
+    where
+        F: std::fmt::Write,
+    {
+        self.format_header(&mut f, include_authors, include_title)?;
+
+        match &self.active_path {
+            Some(path) => {
+                for s in self.root.iter_path_text(&path, "\n") {
+                    write!(f, "{}", s)?;
+                }
+            }
+            None => {
+                for s in self.root.iter_pieces() {
+                    write!(f, "{}", s)?;
+                }
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Format the prompt used to generate the node at `path`. This is exactly
+    /// what [`Story::format_full`] writes when that node is the (empty) head,
+    /// so the node's text is the completion of the prompt.
+    ///
+    /// # Panics
+    /// - If the path is empty or invalid.
+    pub fn format_prompt<F>(
+        &self,
+        mut f: F,
+        path: &[usize],
+        include_authors: bool,
+        include_title: bool,
+    ) -> std::fmt::Result
+    where
+        F: std::fmt::Write,
+    {
+        assert!(self.root.is_valid_path(path) && !path.is_empty());
+
+        self.format_header(&mut f, include_authors, include_title)?;
+
+        let parent = &path[..path.len() - 1];
+        for s in self.root.iter_path_text(parent, "\n") {
+            write!(f, "{}", s)?;
+        }
+        writeln!(f)
+    }
+
+    /// Format the title and authors, if included, for a prompt.
+    fn format_header<F>(
+        &self,
+        mut f: F,
+        include_authors: bool,
+        include_title: bool,
+    ) -> std::fmt::Result
     where
         F: std::fmt::Write,
     {
@@ -258,19 +1454,6 @@ impl Story {
             write!(f, "\n")?;
         }
 
-        match &self.active_path {
-            Some(path) => {
-                for s in self.root.iter_path_text(&path, "\n") {
-                    write!(f, "{}", s)?;
-                }
-            }
-            None => {
-                for s in self.root.iter_pieces() {
-                    write!(f, "{}", s)?;
-                }
-            }
-        };
-
         Ok(())
     }
 
@@ -348,10 +1531,112 @@ mod tests {
             .iter()
             .enumerate()
             .for_each(|(id, &author)| {
-                assert_eq!(Some(id as u8), story.get_author(author));
+                assert_eq!(Some(id as AuthorIndex), story.get_author(author));
             });
     }
 
+    #[test]
+    fn test_many_authors() {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        for i in 1..300 {
+            assert_eq!(story.add_author(format!("Author {}", i)), Some(i));
+        }
+        story.add_paragraph(299, ["Hello"]);
+        assert_eq!(story.head().author_id, 299);
+
+        let json = serde_json::to_string(&story).unwrap();
+        let story: Story = serde_json::from_str(&json).unwrap();
+        assert_eq!(story.get_author("Author 299"), Some(299));
+        assert_eq!(story.author_name(299), Some("Author 299"));
+
+        // There can be as many authors as an index can count, but no more.
+        let mut story = story;
+        for i in 300..=AuthorIndex::MAX {
+            story.add_author(format!("Author {}", i));
+        }
+        assert_eq!(story.add_author("One too many"), None);
+        assert_eq!(story.add_author("Alice"), Some(0));
+    }
+
+    #[test]
+    fn test_author_profile() {
+        let mut story = Story::new("Test".to_string(), "Jane Doe".to_string());
+        let gpt = story.add_model_author("gpt-4o").unwrap();
+
+        let jane = story.author_profile(0);
+        assert_eq!(jane.display_name, "Jane Doe");
+        assert_eq!(jane.initials, "JD");
+        assert_eq!(jane.role, AuthorRole::Human);
+        assert_eq!(story.author_profile(gpt).initials, "G4");
+
+        let profile = story.author_profile_mut(0).unwrap();
+        profile.display_name = "J. Doe".to_string();
+        profile.color = Some([255, 0, 0]);
+        let jane = story.author_profile(0);
+        assert_eq!(jane.display_name, "J. Doe");
+        assert_eq!(jane.initials, "JD");
+        assert_eq!(jane.color, Some([255, 0, 0]));
+
+        // Old stories have no profiles.
+        let story = Story::new("Test".to_string(), "".to_string());
+        assert_eq!(
+            story.author_profile(0).display_name,
+            crate::consts::DEFAULT_AUTHOR
+        );
+    }
+
+    #[test]
+    fn test_cut_paste_between_stories() {
+        let mut a = Story::new("A".to_string(), "Alice".to_string());
+        a.add_paragraph("Alice", ["Hello"]);
+        let gpt = a.add_model_author("gpt").unwrap();
+        a.add_paragraph(gpt, ["World"]);
+
+        let mut b = Story::new("B".to_string(), "Bob".to_string());
+        b.add_paragraph("Bob", ["Hi"]);
+
+        // Cut "Hello" and everything below it.
+        a.active_path = Some(vec![0]);
+        let clipping = a.cut_head().unwrap();
+        assert_eq!(a.root.count(), 1);
+        assert_eq!(clipping.node.count(), 2);
+
+        b.paste(clipping);
+        let pasted = &b.head().children[0];
+        assert_eq!(b.author_name(pasted.author_id), Some("Alice"));
+        let child = &pasted.children[0];
+        assert_eq!(b.author_name(child.author_id), Some("gpt"));
+        assert_eq!(b.author_profile(child.author_id).role, AuthorRole::Model);
+        assert_eq!(b.get_author("Bob"), Some(0));
+    }
+
+    #[test]
+    fn test_branch_to_story() {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.add_author("Unused");
+        let gpt = story.add_model_author("gpt").unwrap();
+        story.add_paragraph("Alice", ["a"]);
+        story.add_paragraph(gpt, ["b"]);
+        story.add_paragraph("Alice", ["c"]);
+        // A sibling of "b" that should not be copied.
+        story.active_path = Some(vec![0]);
+        story.add_paragraph("Alice", ["d"]);
+
+        let branch = story.branch_to_story(&[0, 0], "Branch".to_string());
+        assert_eq!(branch.title, "Branch");
+        assert_eq!(branch.root.count(), 4);
+        assert_eq!(branch.active_path(), &[0, 0]);
+        assert_eq!(branch.head().to_string(), "b");
+        assert_eq!(branch.head().children[0].to_string(), "c");
+        assert_eq!(branch.authors().count(), 2);
+        assert_eq!(branch.author_name(branch.head().author_id), Some("gpt"));
+        assert_eq!(
+            branch.author_profile(branch.head().author_id).role,
+            AuthorRole::Model
+        );
+        assert_ne!(story.root.meta.id(), branch.root.meta.id());
+    }
+
     // This tests we don't break backwards compatibility with the old format.
     #[test]
     fn test_story_deserialize() {
@@ -373,4 +1658,367 @@ mod tests {
         assert_eq!(len, 7453);
         // We're not checking the format or display because they may change.
     }
+
+    #[test]
+    fn test_follow_best_path() {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["a"]);
+        story.add_paragraph("Alice", ["b"]);
+        story.set_active_path(vec![]);
+        story.add_paragraph("Alice", ["c"]);
+        story.add_paragraph("Alice", ["d"]);
+        story.add_paragraph("Alice", ["e"]);
+        story.set_active_path(vec![1]);
+        story.add_paragraph("Alice", ["f"]);
+
+        story.follow_best_path();
+        assert_eq!(story.active_path(), &[0, 0]);
+
+        story.node_mut(&[1]).unwrap().rating = Some(4);
+        story.node_mut(&[1, 1]).unwrap().rating = Some(2);
+        story.follow_best_path();
+        assert_eq!(story.active_path(), &[1, 1]);
+        assert_eq!(story.head().text, "f");
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["a"]);
+        story.set_active_path(vec![]);
+        story.add_paragraph("Alice", ["b"]);
+        story.set_active_path(vec![]);
+        story.add_paragraph("Alice", ["c"]);
+        story.add_paragraph("Alice", ["d"]);
+        story.node_mut(&[0]).unwrap().rating = Some(1);
+        story.node_mut(&[1]).unwrap().flags.rejected = true;
+        story.node_mut(&[2, 0]).unwrap().rating = Some(2);
+
+        // The head moves from [2, 0] to [0, 0] as its aunts are removed.
+        assert_eq!(story.prune_below(2), 1);
+        assert_eq!(story.active_path(), &[1, 0]);
+        assert_eq!(story.prune_rejected(), 1);
+        assert_eq!(story.active_path(), &[0, 0]);
+        assert_eq!(story.head().text, "d");
+
+        // Pruning the head moves it to the closest surviving ancestor.
+        assert_eq!(story.prune_below(3), 1);
+        assert_eq!(story.active_path(), &[0]);
+        assert_eq!(story.head().text, "c");
+    }
+
+    #[test]
+    fn test_format_prompt() {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["Hello"]);
+        story.add_paragraph("Alice", [" World"]);
+        story.add_empty_paragraph("Alice");
+
+        for (authors, title) in [(false, false), (true, true)] {
+            let mut full = String::new();
+            story.format_full(&mut full, authors, title).unwrap();
+            let mut prompt = String::new();
+            story
+                .format_prompt(&mut prompt, story.active_path(), authors, title)
+                .unwrap();
+            assert_eq!(prompt, full);
+        }
+
+        let mut prompt = String::new();
+        story
+            .format_prompt(&mut prompt, &[0], false, false)
+            .unwrap();
+        assert_eq!(prompt, "\n");
+    }
+
+    #[test]
+    fn test_notes() {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["Hello"]);
+        story.head_mut().notes = "Too short.\nExpand.".to_string();
+        story.head_mut().add_tag("draft");
+        story.add_paragraph("Alice", ["World"]);
+        story.head_mut().add_tag("canon");
+        story.head_mut().add_tag("draft");
+
+        assert_eq!(
+            story.tags().into_iter().collect::<Vec<_>>(),
+            ["canon", "draft"]
+        );
+
+        // Notes are never part of the story text or prompts.
+        assert!(!story.to_string().contains("Expand"));
+        let mut markdown = String::new();
+        story.format_markdown(&mut markdown, false).unwrap();
+        assert_eq!(markdown, story.to_string());
+
+        let mut markdown = String::new();
+        story.format_markdown(&mut markdown, true).unwrap();
+        assert!(markdown.ends_with("Hello\n\n> Too short.\n> Expand.\n\nWorld"));
+    }
+
+    #[test]
+    fn test_bookmarks() {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["canon"]);
+        story.add_bookmark("Canon");
+        story.set_active_path(vec![]);
+        story.add_paragraph("Alice", ["experiment"]);
+        story.add_bookmark("Experiment");
+        assert_eq!(story.bookmarks().len(), 2);
+
+        assert!(story.go_to_bookmark(0));
+        assert_eq!(story.head().text, "canon");
+        // Bookmarks follow nodes when paths change.
+        story.set_active_path(vec![]);
+        story.root.children.swap(0, 1);
+        assert!(story.go_to_bookmark(0));
+        assert_eq!(story.active_path(), &[1]);
+
+        // Adding a bookmark with an existing name moves it.
+        story.add_bookmark("Experiment");
+        assert_eq!(story.bookmarks().len(), 2);
+        story.set_active_path(vec![]);
+        assert!(story.go_to_bookmark(1));
+        assert_eq!(story.head().text, "canon");
+
+        // Bookmarks to deleted nodes can't be followed.
+        story.decapitate();
+        assert!(!story.go_to_bookmark(1));
+        assert!(!story.go_to_bookmark(2));
+        assert!(story.remove_bookmark(1).is_some());
+        assert!(story.remove_bookmark(1).is_none());
+    }
+
+    #[test]
+    fn test_history() {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["a"]);
+        story.add_paragraph("Alice", ["b"]);
+        story.root.add_child(Node::default());
+        story.root.children[1].extend_strings(["c"]);
+        assert!(!story.history().can_go_back());
+
+        story.set_active_path(vec![1]);
+        story.set_active_path(vec![1]);
+        story.set_active_path(vec![0]);
+        assert!(story.go_back());
+        assert_eq!(story.head().text, "c");
+        assert!(story.go_back());
+        assert_eq!(story.head().text, "b");
+        assert!(!story.go_back());
+        assert!(story.go_forward());
+        assert!(story.go_forward());
+        assert_eq!(story.head().text, "a");
+        assert!(!story.history().can_go_forward());
+
+        // Navigating clears forward history.
+        assert!(story.go_back());
+        story.set_active_path(vec![0, 0]);
+        assert!(!story.history().can_go_forward());
+
+        // Deleted nodes are skipped.
+        story.set_active_path(vec![0]);
+        story.root.children.remove(1);
+        assert!(story.go_back());
+        assert_eq!(story.head().text, "b");
+        assert!(!story.go_back());
+    }
+
+    #[test]
+    fn test_step() {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["a"]);
+        story.add_paragraph("Alice", ["a0"]);
+        story.add_paragraph("Alice", ["a00"]);
+        story.set_active_path(vec![0]);
+        story.add_paragraph("Alice", ["a1"]);
+        story.add_paragraph("Alice", ["a10"]);
+        story.set_active_path(vec![0, 0]);
+
+        assert!(!story.step(Step::FirstSibling));
+        assert!(!story.step(Step::PreviousSibling));
+        assert!(story.step(Step::NextSibling));
+        assert_eq!(story.head().text, "a1");
+        assert!(!story.step(Step::NextSibling));
+        assert!(story.step(Step::FirstSibling));
+        assert_eq!(story.active_path(), &[0, 0]);
+
+        // Going down follows the child that was last visited.
+        assert!(story.step(Step::NextSibling));
+        assert!(story.step(Step::Parent));
+        assert!(story.step(Step::Child));
+        assert_eq!(story.head().text, "a1");
+        assert!(story.step(Step::Parent));
+        assert!(story.step(Step::Parent));
+        assert!(story.step(Step::Deepest));
+        assert_eq!(story.head().text, "a10");
+        assert!(!story.step(Step::Deepest));
+
+        // Deleted children fall back to the first child.
+        story.step(Step::Parent);
+        story.step(Step::Parent);
+        story.root.children[0].children.remove(1);
+        assert!(story.step(Step::Deepest));
+        assert_eq!(story.head().text, "a00");
+
+        story.set_active_path(vec![]);
+        assert!(!story.step(Step::Parent));
+        assert!(!story.step(Step::NextSibling));
+    }
+
+    #[test]
+    fn test_move_node() {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["a"]);
+        story.add_paragraph("Alice", ["a0"]);
+        story.add_paragraph("Alice", ["a00"]);
+        story.set_active_path(vec![]);
+        story.add_paragraph("Alice", ["b"]);
+        let id = |story: &Story, path: &[usize]| {
+            story.root.iter_path_nodes(path).last().unwrap().meta.id()
+        };
+        let (root, a, a0, a00, b) = (
+            id(&story, &[]),
+            id(&story, &[0]),
+            id(&story, &[0, 0]),
+            id(&story, &[0, 0, 0]),
+            id(&story, &[1]),
+        );
+
+        // Nodes can't be moved into themselves or their descendants.
+        assert_eq!(story.move_node(root, b), Err(MoveError::Root));
+        assert_eq!(story.move_node(a, a), Err(MoveError::Descendant));
+        assert_eq!(story.move_node(a, a00), Err(MoveError::Descendant));
+        assert_eq!(story.move_node(a, 0), Err(MoveError::NotFound));
+        // Moving a node to its parent does nothing.
+        assert_eq!(story.move_node(b, root), Ok(()));
+        assert!(!story.can_undo_move());
+
+        // The head follows the moved subtree.
+        story.set_active_path(vec![0, 0, 0]);
+        assert_eq!(story.move_node(a0, b), Ok(()));
+        assert_eq!(story.active_path(), &[1, 0, 0]);
+        assert_eq!(story.head().meta.id(), a00);
+        // Moving a node before the new parent shifts the new parent.
+        assert_eq!(story.move_node(a, b), Ok(()));
+        assert_eq!(story.root.children.len(), 1);
+        assert_eq!(id(&story, &[0, 1]), a);
+        assert_eq!(story.active_path(), &[0, 0, 0]);
+
+        // Undoing puts nodes back where they were, in order.
+        assert!(story.undo_move());
+        assert_eq!(id(&story, &[0]), a);
+        assert_eq!(story.active_path(), &[1, 0, 0]);
+        assert!(story.undo_move());
+        assert_eq!(id(&story, &[0, 0]), a0);
+        assert_eq!(story.active_path(), &[0, 0, 0]);
+        assert!(!story.undo_move());
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["a"]);
+        story.add_paragraph("Alice", ["a0"]);
+        story.set_active_path(vec![]);
+        story.add_paragraph("Alice", ["b"]);
+        story.set_active_path(vec![]);
+        story.add_paragraph("Alice", ["c"]);
+        let bob = story.add_author("Bob").unwrap();
+        for (i, path) in [[0], [1], [2]].iter().enumerate() {
+            let node = story.node_mut(path).unwrap();
+            node.meta.pos = egui::Pos2::new(i as f32 * 100.0, 0.0);
+            node.meta.size = egui::Vec2::splat(50.0);
+        }
+
+        // Lassoing adds to the selection.
+        story.node_mut(&[0]).unwrap().meta.selected = true;
+        let rect = egui::Rect::from_min_max(
+            egui::Pos2::new(10.0, 10.0),
+            egui::Pos2::new(120.0, 20.0),
+        );
+        assert_eq!(story.select_within(rect), 1);
+        assert_eq!(story.selected_paths(), vec![vec![0], vec![1]]);
+
+        // Bulk edits apply to the selected nodes but not their children.
+        assert_eq!(story.tag_selected("draft"), 2);
+        assert_eq!(story.tag_selected("draft"), 0);
+        assert_eq!(story.rate_selected(Some(9)), 2);
+        assert_eq!(story.set_selected_author(bob), 2);
+        let a0 = story.root.iter_path_nodes(&[0, 0]).last().unwrap();
+        assert!(a0.tags.is_empty());
+        assert_eq!(a0.rating, None);
+        assert_ne!(a0.author_id, bob);
+        let a = story.root.iter_path_nodes(&[0]).last().unwrap();
+        assert_eq!(a.tags, ["draft"]);
+        assert_eq!(a.rating, Some(crate::node::MAX_RATING));
+        assert_eq!(a.author_id, bob);
+
+        let mut markdown = String::new();
+        story
+            .format_selected_markdown(&mut markdown, false)
+            .unwrap();
+        let (a, b) = markdown.split_once("\n\n---\n\n").unwrap();
+        assert!(a.ends_with('a') && b.ends_with('b'));
+
+        // Deleting removes the selected subtrees, and with them the selection.
+        story.set_active_path(vec![2]);
+        assert_eq!(story.delete_selected(), 3);
+        assert_eq!(story.selection_len(), 0);
+        assert_eq!(story.head().text, "c");
+        assert_eq!(story.active_path(), &[0]);
+    }
+
+    #[test]
+    fn test_dirty() {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        assert!(!story.is_dirty());
+        assert!(story.check_dirty());
+
+        let fingerprint = story.fingerprint();
+        story.mark_saved(fingerprint);
+        assert!(!story.is_dirty());
+        assert!(!story.check_dirty());
+
+        story.add_paragraph("Alice", ["a"]);
+        assert_ne!(story.fingerprint(), fingerprint);
+        assert!(story.check_dirty());
+        story.mark_saved(story.fingerprint());
+        assert!(!story.check_dirty());
+    }
+
+    #[test]
+    fn test_set_path() {
+        let dir = std::env::temp_dir()
+            .join(format!("weave-set-path-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let path = dir.join("story.json");
+        std::fs::write(&path, "{}").unwrap();
+
+        // The same file named another way has the same path.
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.set_path(dir.join(".").join("story.json"));
+        let set = story.path().map(std::path::Path::to_path_buf);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(set, Some(path));
+    }
+
+    #[test]
+    fn test_dirty_layout() {
+        let mut story = Story::new("Test".to_string(), "Alice".to_string());
+        story.add_paragraph("Alice", ["a"]);
+        story.mark_saved(story.fingerprint());
+
+        // Arranging and navigating the story aren't edits.
+        let node = story.node_mut(&[0]).unwrap();
+        node.meta.pos = egui::Pos2::new(100.0, 50.0);
+        node.meta.size = egui::Vec2::splat(64.0);
+        story.set_active_path(vec![]);
+        assert!(!story.check_dirty());
+
+        story.node_mut(&[0]).unwrap().rating = Some(2);
+        assert!(story.check_dirty());
+    }
 }